@@ -0,0 +1,497 @@
+use proc_macro2::Span;
+use quote::quote;
+use strum::EnumCount;
+use syn::parse::Parse;
+use uievents_code::KeyboardEventCode;
+
+/// Returns an iterator over all variants of [`KeyboardEventCode`] in the order of their declaration.
+///
+/// [`KeyboardEventCode`]: https://docs.rs/uievents-code/latest/uievents_code/enum.KeyboardEventCode.html
+pub(crate) fn variants() -> impl Iterator<Item = KeyboardEventCode> {
+    (0..KeyboardEventCode::COUNT).filter_map(|i| KeyboardEventCode::from_repr(i as u8))
+}
+
+/// Returns the name of the variant of [`KeyboardEventCode`], which coincides with
+/// the value of [`KeyboardEvent.code`], e.g. `KeyA`.
+///
+/// [`KeyboardEventCode`]: https://docs.rs/uievents-code/latest/uievents_code/enum.KeyboardEventCode.html
+/// [`KeyboardEvent.code`]: https://developer.mozilla.org/en-US/docs/Web/API/KeyboardEvent/code
+pub(crate) fn variant_name(code: KeyboardEventCode) -> String {
+    format!("{:?}", code)
+}
+
+/// Returns the name of the `&'static str` constant from [`uievents_code`] that corresponds
+/// to the variant of [`KeyboardEventCode`], e.g. `KEY_A` for `KeyA`.
+///
+/// [`uievents_code`]: https://docs.rs/uievents-code/latest/uievents_code/
+/// [`KeyboardEventCode`]: https://docs.rs/uievents-code/latest/uievents_code/enum.KeyboardEventCode.html
+pub(crate) fn constant_name(code: KeyboardEventCode) -> String {
+    let variant_name = variant_name(code);
+    let mut constant_name = String::with_capacity(variant_name.len() * 2);
+    for (i, c) in variant_name.chars().enumerate() {
+        if i != 0 && c.is_ascii_uppercase() {
+            constant_name.push('_');
+        }
+        constant_name.push(c.to_ascii_uppercase());
+    }
+    constant_name
+}
+
+/// Computes the [Levenshtein distance] between two strings.
+///
+/// [Levenshtein distance]: https://en.wikipedia.org/wiki/Levenshtein_distance
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev_row = (0..=b.len()).collect::<Vec<_>>();
+    let mut row = vec![0; b.len() + 1];
+    for (i, a_char) in a.chars().enumerate() {
+        row[0] = i + 1;
+        for (j, &b_char) in b.iter().enumerate() {
+            let substitution_cost = if a_char == b_char { 0 } else { 1 };
+            row[j + 1] = (prev_row[j] + substitution_cost)
+                .min(prev_row[j + 1] + 1)
+                .min(row[j] + 1);
+        }
+        std::mem::swap(&mut prev_row, &mut row);
+    }
+    prev_row[b.len()]
+}
+
+/// Returns the error for an unknown key code, with a suggestion of the most similar
+/// known key code, if any.
+///
+/// The suggestion is written in the same form as the unknown key code, i.e. as the name of
+/// a `&'static str` constant (e.g. `KEY_W`) if the unknown key code is written in
+/// upper snake case and as the name of a variant (e.g. `KeyW`) otherwise.
+fn unknown_key_code_error(span: Span, name: &str) -> syn::Error {
+    let is_constant_name = name
+        .chars()
+        .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+    let candidate_name = |code| {
+        if is_constant_name {
+            constant_name(code)
+        } else {
+            variant_name(code)
+        }
+    };
+    // Typos in the case of letters are the cheapest ones
+    let distance = |candidate: &str| {
+        edit_distance(&name.to_ascii_lowercase(), &candidate.to_ascii_lowercase()) * 2
+            + usize::from(name != candidate)
+    };
+    // Roughly one typo per three characters is tolerated
+    let max_distance = (name.chars().count() / 3).max(1) * 2 + 1;
+    let suggestion = variants()
+        .map(candidate_name)
+        .map(|candidate| (distance(&candidate), candidate))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .min_by_key(|&(distance, _)| distance);
+    match suggestion {
+        Some((_, suggestion)) => syn::Error::new(
+            span,
+            format!(
+                "unknown key code `{}`, did you mean `{}`?",
+                name, suggestion
+            ),
+        ),
+        None => syn::Error::new(span, format!("unknown key code `{}`", name)),
+    }
+}
+
+/// Key identifier that corresponds to some [`KeyboardEvent.code`].
+///
+/// It can be written as either of
+///
+/// * a path to a variant of [`KeyboardEventCode`], e.g. `KeyboardEventCode::KeyA`;
+/// * a path to a `&'static str` constant from [`uievents_code`], e.g. `KEY_A`;
+/// * a string literal, e.g. `"KeyA"`.
+///
+/// Paths are resolved by their last segment, so they don't have to be in scope. A path
+/// whose last segment is not a known key code is kept as is, e.g. for user-defined constants,
+/// unless it names a variant of [`KeyboardEventCode`], which is then reported as unknown.
+///
+/// [`KeyboardEvent.code`]: https://developer.mozilla.org/en-US/docs/Web/API/KeyboardEvent/code
+/// [`KeyboardEventCode`]: https://docs.rs/uievents-code/latest/uievents_code/enum.KeyboardEventCode.html
+/// [`uievents_code`]: https://docs.rs/uievents-code/latest/uievents_code/
+pub(crate) struct Key {
+    pub(crate) code: KeyCode,
+    pub(crate) span: Span,
+}
+
+/// Resolved or unresolved [`KeyboardEvent.code`] of a [`Key`].
+///
+/// [`KeyboardEvent.code`]: https://developer.mozilla.org/en-US/docs/Web/API/KeyboardEvent/code
+pub(crate) enum KeyCode {
+    Known(KeyboardEventCode),
+    /// Path whose last segment is not a known key code.
+    ///
+    /// It is emitted unchanged, so it is expected to be a `&'static str` constant in
+    /// `start_keywise_keyboard_handler!` and a constant of type [`KeyboardEventCode`]
+    /// in the other macros.
+    ///
+    /// [`KeyboardEventCode`]: https://docs.rs/uievents-code/latest/uievents_code/enum.KeyboardEventCode.html
+    Unresolved(syn::Path),
+}
+
+impl PartialEq for KeyCode {
+    /// Unresolved key codes are equal if their paths are written identically.
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (KeyCode::Known(code), KeyCode::Known(other_code)) => code == other_code,
+            (KeyCode::Unresolved(path), KeyCode::Unresolved(other_path)) => {
+                quote!(#path).to_string() == quote!(#other_path).to_string()
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Parse for Key {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        if input.peek(syn::LitStr) {
+            let lit = input.parse::<syn::LitStr>()?;
+            let value = lit.value();
+            return match variants().find(|&code| variant_name(code) == value) {
+                Some(code) => Ok(Key {
+                    code: KeyCode::Known(code),
+                    span: lit.span(),
+                }),
+                None => Err(unknown_key_code_error(lit.span(), &value)),
+            };
+        }
+        let path = input.parse::<syn::Path>()?;
+        let last_segment = match path.segments.last() {
+            Some(last_segment) => last_segment,
+            None => return Err(syn::Error::new_spanned(&path, "expected a key code")),
+        };
+        let ident = &last_segment.ident;
+        // E.g. `KeyboardEventCode::KeyWW`, which can only be a misspelled variant
+        let is_variant_path = path
+            .segments
+            .iter()
+            .rev()
+            .nth(1)
+            .map_or(false, |segment| segment.ident == "KeyboardEventCode");
+        match variants().find(|&code| *ident == variant_name(code) || *ident == constant_name(code))
+        {
+            Some(code) => Ok(Key {
+                code: KeyCode::Known(code),
+                span: ident.span(),
+            }),
+            None if is_variant_path => {
+                Err(unknown_key_code_error(ident.span(), &ident.to_string()))
+            }
+            None => Ok(Key {
+                span: ident.span(),
+                code: KeyCode::Unresolved(path),
+            }),
+        }
+    }
+}
+
+impl Key {
+    /// Returns the [`KeyboardEventCode`] of the key, or an error if the key is
+    /// an unresolved path.
+    ///
+    /// `context` describes what requires the known key code, e.g. `key ranges`.
+    ///
+    /// [`KeyboardEventCode`]: https://docs.rs/uievents-code/latest/uievents_code/enum.KeyboardEventCode.html
+    pub(crate) fn known_code(&self, context: &str) -> syn::Result<KeyboardEventCode> {
+        match self.code {
+            KeyCode::Known(code) => Ok(code),
+            KeyCode::Unresolved(_) => Err(syn::Error::new(
+                self.span,
+                format!(
+                    "unknown key code `{}`, {} require known key codes",
+                    self.name(),
+                    context
+                ),
+            )),
+        }
+    }
+
+    /// Returns the human-readable name of the key, i.e. the name of the variant of
+    /// [`KeyboardEventCode`] or the last segment of the unresolved path.
+    ///
+    /// [`KeyboardEventCode`]: https://docs.rs/uievents-code/latest/uievents_code/enum.KeyboardEventCode.html
+    pub(crate) fn name(&self) -> String {
+        match self.code {
+            KeyCode::Known(code) => variant_name(code),
+            KeyCode::Unresolved(ref path) => path
+                .segments
+                .last()
+                .map(|segment| segment.ident.to_string())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Returns the value of [`KeyboardEvent.code`] as a string literal, e.g. `"KeyA"`,
+    /// or the unresolved path, which can be used both as an expression and as a pattern.
+    ///
+    /// [`KeyboardEvent.code`]: https://developer.mozilla.org/en-US/docs/Web/API/KeyboardEvent/code
+    pub(crate) fn to_code_lit(&self) -> proc_macro2::TokenStream {
+        match self.code {
+            KeyCode::Known(code) => {
+                let lit = syn::LitStr::new(&variant_name(code), self.span);
+                quote!(#lit)
+            }
+            KeyCode::Unresolved(ref path) => quote!(#path),
+        }
+    }
+
+    /// Returns the path to the variant of [`KeyboardEventCode`] re-exported by `wasm_keyboard`,
+    /// e.g. `::wasm_keyboard::uievents_code::KeyboardEventCode::KeyA`, or the unresolved path
+    /// checked to be a [`KeyboardEventCode`].
+    ///
+    /// [`KeyboardEventCode`]: https://docs.rs/uievents-code/latest/uievents_code/enum.KeyboardEventCode.html
+    pub(crate) fn to_variant_path(&self) -> proc_macro2::TokenStream {
+        match self.code {
+            KeyCode::Known(code) => {
+                let variant = syn::Ident::new(&variant_name(code), self.span);
+                quote!(::wasm_keyboard::uievents_code::KeyboardEventCode::#variant)
+            }
+            KeyCode::Unresolved(ref path) => quote!(
+                ::core::convert::identity::<::wasm_keyboard::uievents_code::KeyboardEventCode>(#path)
+            ),
+        }
+    }
+}
+
+/// Pattern that matches one or more keys.
+///
+/// It can be written as either of
+///
+/// * a single [key identifier](Key), e.g. `KEY_A`;
+/// * an inclusive range of key identifiers, e.g. `Digit0..=Digit9`, which follows the order of
+///   the variants of [`KeyboardEventCode`];
+/// * a group, i.e. the beginning of the names of the variants of [`KeyboardEventCode`] or of
+///   the `&'static str` constants from [`uievents_code`] followed by `*`, e.g. `Numpad*`.
+///
+/// [`KeyboardEventCode`]: https://docs.rs/uievents-code/latest/uievents_code/enum.KeyboardEventCode.html
+/// [`uievents_code`]: https://docs.rs/uievents-code/latest/uievents_code/
+pub(crate) enum KeyPattern {
+    Key(Key),
+    Range(Key, Key),
+    Group(syn::Ident),
+}
+
+impl Parse for KeyPattern {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        if input.peek(syn::Ident) && input.peek2(syn::Token![*]) {
+            let ident = input.parse()?;
+            input.parse::<syn::Token![*]>()?;
+            return Ok(KeyPattern::Group(ident));
+        }
+        let key = input.parse()?;
+        if !input.peek(syn::Token![..=]) {
+            return Ok(KeyPattern::Key(key));
+        }
+        input.parse::<syn::Token![..=]>()?;
+        Ok(KeyPattern::Range(key, input.parse()?))
+    }
+}
+
+impl KeyPattern {
+    /// Returns whether the pattern can match more than one key.
+    pub(crate) fn is_multi_key(&self) -> bool {
+        !matches!(self, KeyPattern::Key(_))
+    }
+
+    /// Returns the keys that the pattern matches, in the order of the variants of
+    /// [`KeyboardEventCode`].
+    ///
+    /// [`KeyboardEventCode`]: https://docs.rs/uievents-code/latest/uievents_code/enum.KeyboardEventCode.html
+    pub(crate) fn into_keys(self) -> syn::Result<Vec<Key>> {
+        match self {
+            KeyPattern::Key(key) => Ok(vec![key]),
+            KeyPattern::Range(start, end) => {
+                let span = start.span;
+                let start = start.known_code("key ranges")?;
+                let end_span = end.span;
+                let end = end.known_code("key ranges")?;
+                if start as u8 > end as u8 {
+                    return Err(syn::Error::new(
+                        end_span,
+                        format!(
+                            "empty key range, `{}` follows `{}` in `KeyboardEventCode`",
+                            variant_name(start),
+                            variant_name(end)
+                        ),
+                    ));
+                }
+                Ok(variants()
+                    .filter(|&code| (start as u8..=end as u8).contains(&(code as u8)))
+                    .map(|code| Key {
+                        code: KeyCode::Known(code),
+                        span,
+                    })
+                    .collect())
+            }
+            KeyPattern::Group(ident) => {
+                let beginning = ident.to_string();
+                let keys = variants()
+                    .filter(|&code| {
+                        variant_name(code).starts_with(&beginning)
+                            || constant_name(code).starts_with(&beginning)
+                    })
+                    .map(|code| Key {
+                        code: KeyCode::Known(code),
+                        span: ident.span(),
+                    })
+                    .collect::<Vec<_>>();
+                if keys.is_empty() {
+                    return Err(syn::Error::new(
+                        ident.span(),
+                        format!("no key code starts with `{}`", beginning),
+                    ));
+                }
+                Ok(keys)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn suggestion(name: &str) -> String {
+        unknown_key_code_error(Span::call_site(), name).to_string()
+    }
+
+    #[test]
+    fn edit_distance_counts_insertions_deletions_and_substitutions() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("KeyA", "KeyA"), 0);
+        assert_eq!(edit_distance("", "KeyA"), 4);
+        assert_eq!(edit_distance("KeyA", ""), 4);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("Escape", "Escpe"), 1);
+        assert_eq!(edit_distance("ab", "ba"), 2);
+    }
+
+    #[test]
+    fn suggestion_prefers_case_typos() {
+        // `Keya` is one substitution away from many keys but only a case typo away from `KeyA`
+        assert_eq!(
+            suggestion("Keya"),
+            "unknown key code `Keya`, did you mean `KeyA`?"
+        );
+        assert_eq!(
+            suggestion("ENTR"),
+            "unknown key code `ENTR`, did you mean `ENTER`?"
+        );
+    }
+
+    #[test]
+    fn suggestion_follows_the_form_of_the_name() {
+        assert_eq!(
+            suggestion("ARROW_UPP"),
+            "unknown key code `ARROW_UPP`, did you mean `ARROW_UP`?"
+        );
+        assert_eq!(
+            suggestion("ArrowUpp"),
+            "unknown key code `ArrowUpp`, did you mean `ArrowUp`?"
+        );
+    }
+
+    #[test]
+    fn suggestion_for_a_variant_of_a_path() {
+        // The last segment of `KeyboardEventCode::KeyWW`
+        assert_eq!(
+            suggestion("KeyWW"),
+            "unknown key code `KeyWW`, did you mean `KeyW`?"
+        );
+    }
+
+    #[test]
+    fn no_suggestion_for_distant_names() {
+        assert_eq!(suggestion("Jump"), "unknown key code `Jump`");
+        assert_eq!(suggestion("X"), "unknown key code `X`");
+    }
+
+    #[test]
+    fn unknown_string_literal_has_a_suggestion() {
+        let error = syn::parse_str::<Key>(r#""Escpe""#).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "unknown key code `Escpe`, did you mean `Escape`?"
+        );
+    }
+
+    #[test]
+    fn unknown_path_is_kept_unchanged() {
+        let key = syn::parse_str::<Key>("my_keys::JUMP").unwrap();
+        assert!(matches!(key.code, KeyCode::Unresolved(_)));
+        assert_eq!(key.name(), "JUMP");
+        assert_eq!(key.to_code_lit().to_string(), "my_keys :: JUMP");
+    }
+
+    #[test]
+    fn unknown_variant_path_has_a_suggestion() {
+        let error = syn::parse_str::<Key>("KeyboardEventCode::KeyWW")
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "unknown key code `KeyWW`, did you mean `KeyW`?"
+        );
+        let key = syn::parse_str::<Key>("my_keys::KeyWW").unwrap();
+        assert!(matches!(key.code, KeyCode::Unresolved(_)));
+    }
+
+    #[test]
+    fn known_path_is_resolved_by_last_segment() {
+        let key = syn::parse_str::<Key>("uievents_code::KEY_A").unwrap();
+        assert!(key.code == KeyCode::Known(KeyboardEventCode::KeyA));
+        let key = syn::parse_str::<Key>("KeyboardEventCode::KeyA").unwrap();
+        assert!(key.code == KeyCode::Known(KeyboardEventCode::KeyA));
+    }
+
+    #[test]
+    fn range_bounds_must_be_known() {
+        let pattern = syn::parse_str::<KeyPattern>("Digit0..=MY_KEY").unwrap();
+        let error = pattern.into_keys().err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "unknown key code `MY_KEY`, key ranges require known key codes"
+        );
+    }
+
+    #[test]
+    fn range_includes_both_bounds() {
+        let pattern = syn::parse_str::<KeyPattern>("Digit0..=Digit9").unwrap();
+        let keys = pattern.into_keys().unwrap();
+        assert_eq!(keys.len(), 10);
+        assert!(keys[0].code == KeyCode::Known(KeyboardEventCode::Digit0));
+        assert!(keys[9].code == KeyCode::Known(KeyboardEventCode::Digit9));
+    }
+
+    #[test]
+    fn reversed_range_is_rejected() {
+        let pattern = syn::parse_str::<KeyPattern>("Digit9..=Digit0").unwrap();
+        let error = pattern.into_keys().err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "empty key range, `Digit9` follows `Digit0` in `KeyboardEventCode`"
+        );
+    }
+
+    #[test]
+    fn group_matches_variant_and_constant_names() {
+        let keys = |group: &str| {
+            let pattern = syn::parse_str::<KeyPattern>(group).unwrap();
+            pattern.into_keys().unwrap().len()
+        };
+        // `F1` and `F10` to `F19`
+        assert_eq!(keys("F1*"), 11);
+        assert_eq!(keys("Numpad*"), keys("NUMPAD*"));
+    }
+
+    #[test]
+    fn empty_group_is_rejected() {
+        let pattern = syn::parse_str::<KeyPattern>("Jump*").unwrap();
+        let error = pattern.into_keys().err().unwrap();
+        assert_eq!(error.to_string(), "no key code starts with `Jump`");
+    }
+}
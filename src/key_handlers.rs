@@ -1,17 +1,16 @@
 use quote::quote;
-use syn::{parse::Parse, Token, TypeInfer};
+use syn::{parse::Parse, Token};
 
-use crate::{EVENTS, EVENT_COUNT, KEYDOWN_ID, KEYUP_ID};
+#[cfg(feature = "keypress")]
+use crate::KEYPRESS_ID;
+use crate::{key_code::Key, EVENTS, EVENT_COUNT, KEYDOWN_ID, KEYUP_ID};
 
 pub(super) struct Args {
-    /// Syntactic path that corresponds to some [`KeyboardEvent.code`].
-    ///
-    /// It is expected to be a path to a constant of type [`KeyboardEventCode`],
-    /// e.g. `KeyboardEventCode::KeyA`.
+    /// Key identifier that corresponds to some [`KeyboardEvent.code`],
+    /// e.g. `KeyboardEventCode::KeyA`, `KEY_A` or `"KeyA"`.
     ///
     /// [`KeyboardEvent.code`]: https://developer.mozilla.org/en-US/docs/Web/API/KeyboardEvent/code
-    /// [`KeyboardEventCode`]: https://docs.rs/uievents-code/latest/uievents_code/enum.KeyboardEventCode.html
-    pub(super) path: syn::Path,
+    pub(super) key: Key,
     /// The state of the key handler, which is passed by reference to event handlers
     /// on each event.
     ///
@@ -28,117 +27,744 @@ pub(super) struct Args {
     /// [`keyup`]: https://developer.mozilla.org/en-US/docs/Web/API/Element/keyup_event
     /// [`keypress`]: https://developer.mozilla.org/en-US/docs/Web/API/Element/keypress_event
     pub(super) key_event_handlers: [KeyEventHandler; EVENT_COUNT],
+    /// Auto-repeat of the `keydown` event handler on a timer of the key handler, if any.
+    pub(super) repeat: Option<Repeat>,
+    /// The way repeated `keydown` events are told apart from the initial one, if given.
+    pub(super) strategy: Option<Strategy>,
+    /// What happens when an async event handler is called while its previous future
+    /// is still running, if given.
+    pub(super) async_policy: Option<AsyncPolicy>,
+}
+
+/// `repeat = { delay = .., interval = .. }` argument.
+pub(super) struct Repeat {
+    /// The `repeat` identifier, for the errors about the argument.
+    pub(super) name: syn::Ident,
+    /// The number of milliseconds between the initial `keydown` and the first repetition.
+    delay: syn::Expr,
+    /// The number of milliseconds between the subsequent repetitions.
+    interval: syn::Expr,
+}
+
+impl Repeat {
+    /// Parses the value of the argument that follows `repeat = `.
+    fn parse_value(name: syn::Ident, input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let content;
+        syn::braced!(content in input);
+        let parse_field = |field: &str| -> syn::Result<syn::Expr> {
+            if content.parse::<syn::Ident>()? != field {
+                return Err(syn::Error::new(
+                    content.span(),
+                    format!("expected `{}`", field),
+                ));
+            }
+            content.parse::<Token![=]>()?;
+            content.parse()
+        };
+        let delay = parse_field("delay")?;
+        content.parse::<Token![,]>()?;
+        let interval = parse_field("interval")?;
+        content.parse::<Option<Token![,]>>()?;
+        if !content.is_empty() {
+            return Err(content.error("unexpected token"));
+        }
+        Ok(Repeat {
+            name,
+            delay,
+            interval,
+        })
+    }
+}
+
+/// The way the simplified key handler tells the initial `keydown` event of a press apart
+/// from the repeated ones, i.e. `strategy = <cell|repeat|both>` argument.
+pub(super) struct Strategy {
+    /// The `strategy` identifier, for the errors about the argument.
+    pub(super) name: syn::Ident,
+    kind: StrategyKind,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum StrategyKind {
+    /// The key is pressed anew if `is_pressed: Cell<bool>`, which is reset on `keyup`, is unset.
+    Cell,
+    /// The key is pressed anew if [`KeyboardEvent.repeat`] of the event that is being
+    /// dispatched is `false`.
+    ///
+    /// [`KeyboardEvent.repeat`]: https://developer.mozilla.org/en-US/docs/Web/API/KeyboardEvent/repeat
+    Repeat,
+    /// [`StrategyKind::Repeat`] when the event that is being dispatched is available and
+    /// [`StrategyKind::Cell`] otherwise.
+    Both,
+}
+
+impl Strategy {
+    /// Parses the value of the argument that follows `strategy = `.
+    fn parse_value(name: syn::Ident, input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let value = input.parse::<syn::Ident>()?;
+        let kind = match value.to_string().as_str() {
+            "cell" => StrategyKind::Cell,
+            "repeat" => StrategyKind::Repeat,
+            "both" => StrategyKind::Both,
+            _ => {
+                return Err(syn::Error::new(
+                    value.span(),
+                    format!(
+                        "unknown strategy `{}`, expected one of `cell`, `repeat` or `both`",
+                        value
+                    ),
+                ))
+            }
+        };
+        Ok(Strategy { name, kind })
+    }
+
+    /// Extends the token stream with the boolean expression that marks the key as pressed
+    /// in `is_pressed: &Cell<bool>` and checks whether the `keydown` event starts a new press.
+    ///
+    /// `event` is the `Option<&web_sys::KeyboardEvent>` with the event that is being
+    /// dispatched, if any.
+    fn extend_with_new_press_check(
+        strategy: Option<&Strategy>,
+        ts: &mut proc_macro2::TokenStream,
+        is_pressed: &syn::Ident,
+        event: &syn::Ident,
+    ) {
+        let kind = strategy.map_or(StrategyKind::Cell, |strategy| strategy.kind);
+        let is_repeat = quote!(#event.map(|event| event.repeat()));
+        ts.extend(match kind {
+            StrategyKind::Cell => quote!(!#is_pressed.replace(true)),
+            StrategyKind::Repeat => quote!({
+                #is_pressed.set(true);
+                !#is_repeat.unwrap_or(false)
+            }),
+            StrategyKind::Both => quote!({
+                let __was_pressed = #is_pressed.replace(true);
+                match #is_repeat {
+                    ::core::option::Option::Some(__is_repeat) => !__is_repeat,
+                    ::core::option::Option::None => !__was_pressed,
+                }
+            }),
+        });
+    }
+}
+
+/// What happens when an async event handler is called while its previous future is still
+/// running, i.e. `async_policy = <drop|queue|concurrent>` argument.
+pub(super) struct AsyncPolicy {
+    /// The `async_policy` identifier, for the errors about the argument.
+    pub(super) name: syn::Ident,
+    kind: AsyncPolicyKind,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum AsyncPolicyKind {
+    /// The call is ignored.
+    Drop,
+    /// The future of the call runs after the futures of the previous calls complete.
+    Queue,
+    /// The future of the call runs concurrently with the futures of the previous calls.
+    Concurrent,
+}
+
+impl AsyncPolicy {
+    /// Parses the value of the argument that follows `async_policy = `.
+    fn parse_value(name: syn::Ident, input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let value = input.parse::<syn::Ident>()?;
+        let kind = match value.to_string().as_str() {
+            "drop" => AsyncPolicyKind::Drop,
+            "queue" => AsyncPolicyKind::Queue,
+            "concurrent" => AsyncPolicyKind::Concurrent,
+            _ => {
+                return Err(syn::Error::new(
+                    value.span(),
+                    format!(
+                    "unknown async policy `{}`, expected one of `drop`, `queue` or `concurrent`",
+                    value
+                ),
+                ))
+            }
+        };
+        Ok(AsyncPolicy { name, kind })
+    }
+
+    /// Extends the token stream with the definition of `spawn_local_on_call` function, which
+    /// turns a `FnOnce(S) -> impl Future<Output = ()> + Clone` closure into a `Fn(&S)` one that
+    /// spawns the future with [`wasm_bindgen_futures::spawn_local`] according to the policy.
+    ///
+    /// The closure is cloned on each call, so that the future of each call owns clones of
+    /// the captured variables, and the reference to the state tells the type of the state to
+    /// the closure literal.
+    ///
+    /// [`wasm_bindgen_futures::spawn_local`]: https://docs.rs/wasm-bindgen-futures/latest/wasm_bindgen_futures/fn.spawn_local.html
+    fn extend_with_spawn_local_on_call_fn(
+        policy: Option<&AsyncPolicy>,
+        ts: &mut proc_macro2::TokenStream,
+    ) {
+        let kind = policy.map_or(AsyncPolicyKind::Concurrent, |policy| policy.kind);
+        let body = match kind {
+            AsyncPolicyKind::Drop => quote!(
+                let is_running = ::std::rc::Rc::new(::core::cell::Cell::new(false));
+                move |state| {
+                    if is_running.replace(true) {
+                        return;
+                    }
+                    let future = f.clone()(state.clone());
+                    let is_running = is_running.clone();
+                    ::wasm_bindgen_futures::spawn_local(async move {
+                        future.await;
+                        is_running.set(false);
+                    });
+                }
+            ),
+            AsyncPolicyKind::Queue => quote!(
+                let queue = ::std::rc::Rc::new(::core::cell::RefCell::new(
+                    ::std::collections::VecDeque::<
+                        ::core::pin::Pin<::std::boxed::Box<dyn ::core::future::Future<Output = ()>>>,
+                    >::new(),
+                ));
+                let is_running = ::std::rc::Rc::new(::core::cell::Cell::new(false));
+                move |state| {
+                    queue.borrow_mut().push_back(::std::boxed::Box::pin(f.clone()(state.clone())));
+                    if is_running.replace(true) {
+                        return;
+                    }
+                    let queue = queue.clone();
+                    let is_running = is_running.clone();
+                    ::wasm_bindgen_futures::spawn_local(async move {
+                        loop {
+                            // The queue isn't borrowed across the await point
+                            let future = queue.borrow_mut().pop_front();
+                            match future {
+                                ::core::option::Option::Some(future) => future.await,
+                                ::core::option::Option::None => break,
+                            }
+                        }
+                        is_running.set(false);
+                    });
+                }
+            ),
+            AsyncPolicyKind::Concurrent => {
+                quote!(move |state| ::wasm_bindgen_futures::spawn_local(f.clone()(state.clone())))
+            }
+        };
+        ts.extend(quote!(
+            fn spawn_local_on_call<
+                S: ::core::clone::Clone,
+                F: FnOnce(S) -> FUTURE + ::core::clone::Clone,
+                FUTURE: ::core::future::Future<Output = ()> + 'static,
+            >(
+                _state: &S,
+                f: F,
+            ) -> impl Fn(&S) {
+                #body
+            }
+        ));
+    }
+}
+
+/// Extends the token stream with the definition of the trait named `trait_name` for the return
+/// values of the event handlers, which tell whether the event handler handled the event or
+/// declined it: `()`, `true` and `ControlFlow::Break(())` mean that it did, while `false` and
+/// `ControlFlow::Continue(())` mean that it declined the event.
+pub(super) fn extend_with_outcome_trait(
+    ts: &mut proc_macro2::TokenStream,
+    trait_name: &syn::Ident,
+) {
+    ts.extend(quote!(
+        /// Return value of an event handler, which tells whether it handled the event
+        /// or declined it.
+        trait #trait_name {
+            fn is_handled(self) -> bool;
+        }
+
+        impl #trait_name for () {
+            fn is_handled(self) -> bool {
+                true
+            }
+        }
+
+        impl #trait_name for bool {
+            fn is_handled(self) -> bool {
+                self
+            }
+        }
+
+        impl #trait_name for ::core::ops::ControlFlow<()> {
+            fn is_handled(self) -> bool {
+                self.is_break()
+            }
+        }
+    ));
+}
+
+/// Name of the property of the JS global object that holds the keyboard event that
+/// [crate::start_keywise_keyboard_handler] is dispatching to a key handler.
+///
+/// The methods of [`wasm_keyboard::KeyHandler`] take no event and return nothing, so the event
+/// is handed over to the key handlers created by [crate::new_primitive_key_handler] and
+/// [crate::new_simplified_key_handler] through the property, and such a key handler declines
+/// the event by clearing it.
+///
+/// [`wasm_keyboard::KeyHandler`]: https://docs.rs/wasm_keyboard/latest/wasm_keyboard/trait.KeyHandler.html
+const DISPATCHED_EVENT_PROPERTY: &str = "__wasm_keyboard_macros_dispatched_event";
+
+/// Extends the token stream with the definition of `dispatch_to_key_handler` associated
+/// function, which calls a method of [`wasm_keyboard::KeyHandler`] while the event, if any,
+/// is the dispatched one (see [`DISPATCHED_EVENT_PROPERTY`]) and returns whether the key handler
+/// handled the event.
+///
+/// Outside of `wasm32` targets, there's no JS global object, so the key handler receives
+/// no event and always handles it.
+///
+/// [`wasm_keyboard::KeyHandler`]: https://docs.rs/wasm_keyboard/latest/wasm_keyboard/trait.KeyHandler.html
+pub(super) fn extend_with_dispatch_to_key_handler_fn(ts: &mut proc_macro2::TokenStream) {
+    ts.extend(quote!(
+        fn dispatch_to_key_handler(
+            event: ::core::option::Option<&::web_sys::KeyboardEvent>,
+            handle: impl FnOnce(),
+        ) -> bool {
+            if !::core::cfg!(target_arch = "wasm32") {
+                handle();
+                return true;
+            }
+            let global = ::web_sys::js_sys::global();
+            let property = ::wasm_bindgen::JsValue::from_str(#DISPATCHED_EVENT_PROPERTY);
+            // The key handler can dispatch other events while handling this one
+            let previous = ::web_sys::js_sys::Reflect::get(&global, &property)
+                .unwrap_or(::wasm_bindgen::JsValue::UNDEFINED);
+            let undefined = ::wasm_bindgen::JsValue::UNDEFINED;
+            let event = event.map_or(&undefined, ::core::convert::AsRef::as_ref);
+            let _ = ::web_sys::js_sys::Reflect::set(&global, &property, event);
+            handle();
+            let is_handled = event.is_undefined()
+                || ::web_sys::js_sys::Reflect::get(&global, &property)
+                    .map_or(true, |event| !event.is_undefined());
+            let _ = ::web_sys::js_sys::Reflect::set(&global, &property, &previous);
+            is_handled
+        }
+    ));
+}
+
+/// Extends the token stream with the definitions of `dispatched_event` function, which returns
+/// the event that is being dispatched to the key handler (see [`DISPATCHED_EVENT_PROPERTY`]),
+/// if any, and `decline_dispatched_event` function, which tells the dispatcher that the key
+/// handler declined the event.
+fn extend_with_dispatched_event_fns(ts: &mut proc_macro2::TokenStream) {
+    ts.extend(quote!(
+        fn dispatched_event() -> ::core::option::Option<::web_sys::KeyboardEvent> {
+            if !::core::cfg!(target_arch = "wasm32") {
+                return ::core::option::Option::None;
+            }
+            let event = ::web_sys::js_sys::Reflect::get(
+                &::web_sys::js_sys::global(),
+                &::wasm_bindgen::JsValue::from_str(#DISPATCHED_EVENT_PROPERTY),
+            )
+            .ok()?;
+            ::wasm_bindgen::JsCast::dyn_into::<::web_sys::KeyboardEvent>(event).ok()
+        }
+        fn decline_dispatched_event() {
+            if ::core::cfg!(target_arch = "wasm32") {
+                let _ = ::web_sys::js_sys::Reflect::set(
+                    &::web_sys::js_sys::global(),
+                    &::wasm_bindgen::JsValue::from_str(#DISPATCHED_EVENT_PROPERTY),
+                    &::wasm_bindgen::JsValue::UNDEFINED,
+                );
+            }
+        }
+    ));
 }
 
 pub struct KeyEventHandler {
     prelude: Vec<syn::Stmt>,
-    closure: syn::ExprClosure,
+    /// The expression that evaluates to the event handler, e.g. a closure, a path to a function
+    /// or a variable holding a closure.
+    callable: syn::Expr,
+}
+
+impl KeyEventHandler {
+    /// Splits the statements of the block of the handler named `name` into the prelude
+    /// and the trailing callable expression.
+    pub(super) fn from_stmts(
+        mut stmts: Vec<syn::Stmt>,
+        name: &str,
+        span: proc_macro2::Span,
+    ) -> syn::Result<Self> {
+        let last = stmts
+            .pop()
+            .ok_or_else(|| syn::Error::new(span, format!("expected `{}` event handler", name)))?;
+
+        match last {
+            syn::Stmt::Expr(callable) => Ok(KeyEventHandler {
+                prelude: stmts,
+                callable,
+            }),
+            last => Err(syn::Error::new_spanned(
+                last,
+                format!(
+                    "last statement of `{}` event handler is expected to be a callable expression \
+                    without a trailing semicolon",
+                    name
+                ),
+            )),
+        }
+    }
+}
+
+impl KeyEventHandler {
+    /// Returns the closure expression of the event handler, if the callable is one.
+    ///
+    /// Only closure expressions can be of `|state, event|` or `async |state|` form, since
+    /// the arity and the asyncness of other callables are unknown to the macro.
+    fn closure(&self) -> Option<&syn::ExprClosure> {
+        match &self.callable {
+            syn::Expr::Closure(closure) => Some(closure),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the closure is of `|state, event|` form, i.e. whether it receives
+    /// the keyboard event along with the state.
+    fn takes_event(&self) -> bool {
+        self.closure()
+            .map_or(false, |closure| closure.inputs.len() == 2)
+    }
+
+    /// Returns whether the closure is of `async |state|` form.
+    fn is_async(&self) -> bool {
+        self.closure()
+            .map_or(false, |closure| closure.asyncness.is_some())
+    }
+
+    /// Extends the token stream with the callable of the event handler wrapped into a function
+    /// from [`Args::extend_with_wrapper_fns`], where `state` is the expression that evaluates to
+    /// a reference to the state passed to the callable.
+    ///
+    /// Since async closures are unstable, the async closure is turned into a closure returning
+    /// an async block.
+    fn extend_with_callable(
+        &self,
+        ts: &mut proc_macro2::TokenStream,
+        state: &proc_macro2::TokenStream,
+    ) {
+        let callable = &self.callable;
+        ts.extend(match self.closure() {
+            Some(syn::ExprClosure {
+                attrs,
+                asyncness: Some(_),
+                capture,
+                inputs,
+                body,
+                ..
+            }) => quote!(without_event(#state, spawn_local_on_call(
+                #state,
+                #(#attrs)* #capture |#inputs| async move { #body }
+            ))),
+            _ if self.takes_event() => quote!(with_event(#state, #callable)),
+            _ => quote!(without_event(#state, #callable)),
+        });
+    }
+
+    /// Extends the token stream with the block of the event handler of [`Args`], where
+    /// the callable is wrapped as in [`KeyEventHandler::extend_with_callable`].
+    fn extend_with_block(
+        &self,
+        ts: &mut proc_macro2::TokenStream,
+        state: &proc_macro2::TokenStream,
+    ) {
+        let prelude_statements = &self.prelude;
+        let mut callable = proc_macro2::TokenStream::new();
+        self.extend_with_callable(&mut callable, state);
+        ts.extend(quote! {
+            {
+                #(#prelude_statements)*
+                #callable
+            }
+        });
+    }
 }
 
 impl quote::ToTokens for KeyEventHandler {
     fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
         let prelude_statements = &self.prelude;
-        let closure = &self.closure;
+        let callable = &self.callable;
         tokens.extend(quote! {
             {
                 #(#prelude_statements)*
-                #closure
+                #callable
             }
         });
     }
 }
 
+/// Parses `<name> = ` prefix of a named argument.
+pub(super) fn parse_name(input: syn::parse::ParseStream, name: &str) -> syn::Result<()> {
+    input.parse::<Token![,]>()?;
+    if input.parse::<syn::Ident>()? != name {
+        return Err(syn::Error::new(
+            input.span(),
+            format!("expected `{}`", name),
+        ));
+    };
+    input.parse::<Token![=]>()?;
+    Ok(())
+}
+
+/// Parses `, <name> = { .. }` argument with an event handler.
+pub(super) fn parse_event_handler(
+    input: syn::parse::ParseStream,
+    name: &str,
+) -> syn::Result<KeyEventHandler> {
+    parse_name(input, name)?;
+    KeyEventHandler::from_stmts(input.parse::<syn::Block>()?.stmts, name, input.span())
+}
+
 impl Parse for Args {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
-        let path = input.parse()?;
-        input.parse::<Token![,]>()?;
-        let state = {
-            if input.parse::<syn::Ident>()? != "state" {
-                return Err(syn::Error::new(input.span(), "expected `state`"));
-            };
+        let key = input.parse()?;
+        let mut state = None;
+        let mut key_event_handlers: [Option<KeyEventHandler>; EVENT_COUNT] = Default::default();
+        let mut repeat = None;
+        let mut strategy = None;
+        let mut async_policy = None;
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            let name = input.parse::<syn::Ident>()?;
             input.parse::<Token![=]>()?;
-            input.parse()?
-        };
-        // at the moment of writing, `array_try_map` feature is unstable
-        let key_event_handlers: [KeyEventHandler; EVENT_COUNT] = {
-            let mut key_event_handlers_it = EVENTS
-                .map(|event| {
-                    let mut stmts = {
-                        input.parse::<Token![,]>()?;
-                        if input.parse::<syn::Ident>()? != event {
-                            return Err(syn::Error::new(
-                                input.span(),
-                                format!("expected `{}`", event),
-                            ));
-                        };
-                        input.parse::<Token![=]>()?;
-                        input.parse::<syn::Block>()?
+            let name_str = name.to_string();
+            let is_duplicate = match name_str.as_str() {
+                "state" => state.replace(input.parse::<syn::Expr>()?).is_some(),
+                "repeat" => repeat
+                    .replace(Repeat::parse_value(name.clone(), input)?)
+                    .is_some(),
+                "strategy" => strategy
+                    .replace(Strategy::parse_value(name.clone(), input)?)
+                    .is_some(),
+                "async_policy" => async_policy
+                    .replace(AsyncPolicy::parse_value(name.clone(), input)?)
+                    .is_some(),
+                event => match EVENTS.iter().position(|&other| other == event) {
+                    Some(event_id) => {
+                        let stmts = input.parse::<syn::Block>()?.stmts;
+                        key_event_handlers[event_id]
+                            .replace(KeyEventHandler::from_stmts(stmts, event, input.span())?)
+                            .is_some()
                     }
-                    .stmts;
-
-                    let last = stmts.pop().ok_or_else(|| {
-                        syn::Error::new(input.span(), format!("expected `{}` event handler", event))
-                    })?;
-
-                    match last {
-                        syn::Stmt::Expr(syn::Expr::Closure(closure)) => Ok(KeyEventHandler {
-                            prelude: stmts,
-                            closure,
-                        }),
-                        _ => Err(syn::Error::new(
-                            input.span(),
+                    None => {
+                        return Err(syn::Error::new(
+                            name.span(),
                             format!(
-                                "last statement of `{}` event handler is expected to be a closure",
-                                event
+                                "unknown argument `{}`, expected one of `state`, {}, `repeat`, `strategy` or `async_policy`",
+                                name,
+                                EVENTS.map(|event| format!("`{}`", event)).join(", ")
                             ),
-                        )),
+                        ))
                     }
-                })
-                .into_iter();
-            std::array::from_fn(|_i| match key_event_handlers_it.next().unwrap() {
-                Ok(f) => f,
-                Err(e) => panic!("{e}"),
+                },
+            };
+            if is_duplicate {
+                return Err(syn::Error::new(
+                    name.span(),
+                    format!("argument `{}` is specified more than once", name),
+                ));
+            }
+        }
+        // The omitted state is `()` and the omitted event handlers do nothing
+        let state = state.unwrap_or_else(|| syn::parse_quote!(()));
+        let key_event_handlers = key_event_handlers.map(|key_event_handler| {
+            key_event_handler.unwrap_or_else(|| KeyEventHandler {
+                prelude: Vec::new(),
+                callable: syn::parse_quote!(|_: &_| {}),
             })
-        };
+        });
+        for (key_event_handler, event) in key_event_handlers.iter().zip(EVENTS) {
+            let closure = match key_event_handler.closure() {
+                Some(closure) => closure,
+                None => continue,
+            };
+            if closure.asyncness.is_some() && closure.inputs.len() != 1 {
+                return Err(syn::Error::new_spanned(
+                    &closure.inputs,
+                    format!("async `{}` event handler must accept only the state", event),
+                ));
+            }
+        }
+        if let Some(AsyncPolicy { name, .. }) = &async_policy {
+            if !key_event_handlers.iter().any(KeyEventHandler::is_async) {
+                return Err(syn::Error::new(
+                    name.span(),
+                    "`async_policy` argument requires an async event handler",
+                ));
+            }
+        }
         Ok(Args {
-            path,
+            key,
             state,
             key_event_handlers,
+            repeat,
+            strategy,
+            async_policy,
         })
     }
 }
 
 impl Args {
-    // 1 inferred type for state and EVENT_COUNT for event handlers
-    fn inferred_tys() -> [TypeInfer; EVENT_COUNT + 1] {
-        std::array::from_fn(|_i| TypeInfer {
-            underscore_token: <Token![_]>::default(),
-        })
+    /// Returns the name of the first given argument that only [crate::new_simplified_key_handler]
+    /// macro supports, if any.
+    pub(super) fn simplified_only_argument(&self) -> Option<&syn::Ident> {
+        let repeat = self.repeat.as_ref().map(|repeat| &repeat.name);
+        let strategy = self.strategy.as_ref().map(|strategy| &strategy.name);
+        repeat.or(strategy)
+    }
+
+    /// Extends the token stream with the definitions of the functions that wrap the callables
+    /// of the event handlers into `Fn(&S, Option<&web_sys::KeyboardEvent>) -> bool` closures,
+    /// where the event is `None` if the call isn't caused by a keyboard event and the return
+    /// value tells whether the event was handled (see [`extend_with_outcome_trait`]):
+    ///
+    /// * `constrain`, which only helps the type inference of the parameters of such a closure;
+    /// * `without_event`, which turns a `Fn(&S)` callable into such a closure;
+    /// * `spawn_local_on_call` (see [`AsyncPolicy::extend_with_spawn_local_on_call_fn`]),
+    ///   if some of the event handlers is async;
+    /// * `with_event`, which turns a `Fn(&S, Option<&web_sys::KeyboardEvent>)` closure into
+    ///   such a closure, if some of the event handlers receives the keyboard event.
+    ///
+    /// Each of them takes a reference to the state, which tells the type of the state to
+    /// the closure literals.
+    ///
+    /// It also defines `handle_dispatched_event`, which turns such a closure into a `Fn(&S)` one
+    /// for [`wasm_keyboard::implementors::KeyHandler`] that receives the event that is being
+    /// dispatched (see [`DISPATCHED_EVENT_PROPERTY`]) and declines it if the closure does.
+    ///
+    /// [`wasm_keyboard::implementors::KeyHandler`]: https://docs.rs/wasm_keyboard/latest/wasm_keyboard/implementors/struct.KeyHandler.html
+    fn extend_with_wrapper_fns(&self, ts: &mut proc_macro2::TokenStream) {
+        extend_with_outcome_trait(ts, &quote::format_ident!("Outcome"));
+        extend_with_dispatched_event_fns(ts);
+        ts.extend(quote!(
+            #[allow(dead_code)]
+            fn constrain<
+                S,
+                F: Fn(&S, ::core::option::Option<&::web_sys::KeyboardEvent>) -> bool,
+            >(
+                _state: &S,
+                f: F,
+            ) -> F {
+                f
+            }
+            #[allow(dead_code)]
+            fn without_event<S, R: Outcome, F: Fn(&S) -> R>(
+                _state: &S,
+                f: F,
+            ) -> impl Fn(&S, ::core::option::Option<&::web_sys::KeyboardEvent>) -> bool
+            {
+                move |state, _event| Outcome::is_handled(f(state))
+            }
+            fn handle_dispatched_event<
+                S,
+                F: Fn(&S, ::core::option::Option<&::web_sys::KeyboardEvent>) -> bool,
+            >(
+                f: F,
+            ) -> impl Fn(&S) {
+                move |state| {
+                    let event = dispatched_event();
+                    if !f(state, event.as_ref()) {
+                        decline_dispatched_event();
+                    }
+                }
+            }
+        ));
+        if self
+            .key_event_handlers
+            .iter()
+            .any(KeyEventHandler::is_async)
+        {
+            AsyncPolicy::extend_with_spawn_local_on_call_fn(self.async_policy.as_ref(), ts);
+        }
+        if self
+            .key_event_handlers
+            .iter()
+            .any(KeyEventHandler::takes_event)
+        {
+            ts.extend(quote!(
+                fn with_event<
+                    S,
+                    R: Outcome,
+                    F: Fn(&S, ::core::option::Option<&::web_sys::KeyboardEvent>) -> R,
+                >(
+                    _state: &S,
+                    f: F,
+                ) -> impl Fn(&S, ::core::option::Option<&::web_sys::KeyboardEvent>) -> bool
+                {
+                    move |state, event| Outcome::is_handled(f(state, event))
+                }
+            ));
+        }
+    }
+
+    /// Extends the token stream with the expression that creates
+    /// [`wasm_keyboard::implementors::KeyHandler`] for the key from the state and
+    /// the `Fn(&S, Option<&web_sys::KeyboardEvent>) -> bool` event handlers bound to
+    /// the variables named after the events (see [`Args::extend_with_wrapper_fns`]).
+    ///
+    /// [`wasm_keyboard::implementors::KeyHandler`]: https://docs.rs/wasm_keyboard/latest/wasm_keyboard/implementors/struct.KeyHandler.html
+    fn extend_with_key_handler_new(&self, ts: &mut proc_macro2::TokenStream, state: &syn::Ident) {
+        let variant_path = self.key.to_variant_path();
+        let events = EVENTS.map(|event| quote::format_ident!("{}", event));
+        let inferred_tys = EVENTS.map(|_| quote!(_));
+        ts.extend(quote!(
+            ::wasm_keyboard::implementors::KeyHandler::<{ #variant_path as u8 }, _, #(#inferred_tys),*>::new(
+                #state,
+                #(handle_dispatched_event(#events)),*
+            )
+        ));
     }
 
     /// Responsible for implementation of [crate::new_primitive_key_handler] macro.
     pub(super) fn extend_with_primitive_key_handler_expr(&self, ts: &mut proc_macro2::TokenStream) {
-        let &Self {
-            ref path,
-            ref state,
-            ref key_event_handlers,
+        let Self {
+            state,
+            key_event_handlers,
+            ..
         } = self;
 
-        let inferred_tys = Self::inferred_tys();
+        let events = EVENTS.map(|event| quote::format_ident!("{}", event));
+        let mut items = proc_macro2::TokenStream::new();
+        self.extend_with_wrapper_fns(&mut items);
+        let key_event_handlers = key_event_handlers.iter().map(|key_event_handler| {
+            let mut block = proc_macro2::TokenStream::new();
+            key_event_handler.extend_with_block(&mut block, &quote!(&__state));
+            block
+        });
+        let mut key_handler = proc_macro2::TokenStream::new();
+        self.extend_with_key_handler_new(&mut key_handler, &quote::format_ident!("__state"));
 
         ts.extend(quote!(
-            ::wasm_keyboard::implementors::KeyHandler::<
-                { ::core::convert::identity::<::wasm_keyboard::uievents_code::KeyboardEventCode>(#path) as u8 },
-                #( #inferred_tys ),*
-            >::new(#state, #(#key_event_handlers),*)
+            {
+                #items
+                let __state = #state;
+                #(let #events = #key_event_handlers;)*
+                #key_handler
+            }
         ));
     }
 
     pub(super) fn extend_with_simplified_key_handler_expr(self, ts: &mut proc_macro2::TokenStream) {
+        if self.repeat.is_some() {
+            return self.extend_with_repeating_key_handler_expr(ts);
+        }
+        let mut items = proc_macro2::TokenStream::new();
+        self.extend_with_wrapper_fns(&mut items);
+        let mut key_handler = proc_macro2::TokenStream::new();
+        self.extend_with_key_handler_new(&mut key_handler, &quote::format_ident!("__state"));
+
         let Self {
-            ref path,
             state,
-            mut key_event_handlers,
+            key_event_handlers,
+            strategy,
+            ..
         } = self;
 
         let state = quote!(
@@ -150,46 +776,58 @@ impl Args {
             // at the moment, enumerate on arrays in not implemented
             let key_event_handlers_syn: [proc_macro2::TokenStream; EVENT_COUNT] =
                 std::array::from_fn(|i| {
-                    let &mut KeyEventHandler {
-                        ref prelude,
-                        ref mut closure,
-                    } = &mut key_event_handlers[i];
-                    // We need to make the closure capture
-                    // the variables from the outer closure by reference
-                    // in case if captured them by value.
-                    closure.capture = None;
+                    let key_event_handler = &key_event_handlers[i];
+                    let prelude = &key_event_handler.prelude;
+                    let mut callable = proc_macro2::TokenStream::new();
+                    key_event_handler.extend_with_callable(&mut callable, &quote!(&__state.1));
+                    let mut is_new_press = proc_macro2::TokenStream::new();
+                    Strategy::extend_with_new_press_check(
+                        strategy.as_ref(),
+                        &mut is_new_press,
+                        &quote::format_ident!("is_pressed"),
+                        &quote::format_ident!("event"),
+                    );
+                    // The callable is evaluated once and moved into the closure, which calls
+                    // through it, so it can be any expression rather than a closure literal.
                     match i {
                         KEYDOWN_ID => quote! {
                             {
                                 #( #prelude )*
-                                move |(ref is_pressed, ref state)| {
-                                    let handler = #closure;
-                                    if !is_pressed.get() {
-                                        is_pressed.set(true);
-                                        handler(state);
+                                let handler = #callable;
+                                constrain(&__state, move |(ref is_pressed, ref state), event| {
+                                    if !#is_new_press {
+                                        // The repeats belong to the press, which was handled
+                                        return true;
+                                    }
+                                    let is_handled = handler(state, event);
+                                    if !is_handled {
+                                        // The declined press doesn't receive `keyup`
+                                        is_pressed.set(false);
                                     }
-                                }
+                                    is_handled
+                                })
                             }
                         },
                         KEYUP_ID => quote! {
                             {
                                 #( #prelude )*
-                                move |(ref is_pressed, ref state)| {
-                                    let handler = #closure;
+                                let handler = #callable;
+                                constrain(&__state, move |(ref is_pressed, ref state), event| {
                                     is_pressed.set(false);
-                                    handler(state);
-                                }
+                                    handler(state, event)
+                                })
                             }
                         },
                         #[cfg(feature = "keypress")]
                         KEYPRESS_ID => quote! {
                             {
                                 #( #prelude )*
-                                move |(ref is_pressed, ref state)| {
-                                    let handler = #closure;
-                                    handler(state);
+                                let handler = #callable;
+                                constrain(&__state, move |(ref is_pressed, ref state), event| {
+                                    let is_handled = handler(state, event);
                                     is_pressed.set(false);
-                                }
+                                    is_handled
+                                })
                             }
                         },
                         _ => unreachable!(),
@@ -197,14 +835,386 @@ impl Args {
                 });
             key_event_handlers_syn
         };
+        let events = EVENTS.map(|event| quote::format_ident!("{}", event));
 
-        let inferred_tys = Self::inferred_tys();
+        ts.extend(quote!(
+            {
+                #items
+                let __state = #state;
+                #(let #events = #key_event_handlers;)*
+                #key_handler
+            }
+        ));
+    }
+
+    /// Responsible for implementation of [crate::new_simplified_key_handler] macro
+    /// with `repeat` argument.
+    ///
+    /// Unlike the ordinary simplified key handler, the event handlers are called from timers,
+    /// so they are stored along with the state behind [`Rc`][`std::rc::Rc`].
+    fn extend_with_repeating_key_handler_expr(&self, ts: &mut proc_macro2::TokenStream) {
+        let Self {
+            state,
+            key_event_handlers,
+            repeat,
+            strategy,
+            ..
+        } = self;
+        let Repeat {
+            delay, interval, ..
+        } = repeat.as_ref().expect("`repeat` argument is given");
+
+        let events = EVENTS.map(|event| quote::format_ident!("{}", event));
+        let tys = EVENTS.map(|event| quote::format_ident!("{}", event.to_uppercase()));
+        let mut is_new_press = proc_macro2::TokenStream::new();
+        Strategy::extend_with_new_press_check(
+            strategy.as_ref(),
+            &mut is_new_press,
+            &quote::format_ident!("is_pressed"),
+            &quote::format_ident!("event"),
+        );
+        #[cfg(feature = "keypress")]
+        let keypress_handler = quote!(
+            let keypress = constrain(&__inner, |inner, event| (inner.keypress)(&inner.state, event));
+        );
+        #[cfg(not(feature = "keypress"))]
+        let keypress_handler = quote!();
+        let mut items = proc_macro2::TokenStream::new();
+        self.extend_with_wrapper_fns(&mut items);
+        let key_event_handlers = key_event_handlers.iter().map(|key_event_handler| {
+            let mut block = proc_macro2::TokenStream::new();
+            key_event_handler.extend_with_block(&mut block, &quote!(&__state));
+            block
+        });
+        let mut key_handler = proc_macro2::TokenStream::new();
+        self.extend_with_key_handler_new(&mut key_handler, &quote::format_ident!("__inner"));
 
         ts.extend(quote!(
-            ::wasm_keyboard::implementors::KeyHandler::<
-                { ::core::convert::identity::<::wasm_keyboard::uievents_code::KeyboardEventCode>(#path) as u8 },
-                #( #inferred_tys ),*
-            >::new(#state, #(#key_event_handlers),*)
+            {
+                #items
+                struct RepeatingKeyHandlerState<S, #(#tys),*> {
+                    is_pressed: ::core::cell::Cell<bool>,
+                    // Incremented on each release, so that the timers of the previous presses
+                    // stop repeating
+                    generation: ::core::cell::Cell<u32>,
+                    // The initial `keydown` event of the current press, if any, which
+                    // the repetitions pass to the `keydown` event handler
+                    initial_event: ::core::cell::RefCell<::core::option::Option<::web_sys::KeyboardEvent>>,
+                    delay: i32,
+                    interval: i32,
+                    state: S,
+                    #(#events: #tys,)*
+                }
+
+                impl<
+                    S: 'static,
+                    #(#tys: Fn(&S, ::core::option::Option<&::web_sys::KeyboardEvent>) -> bool + 'static),*
+                > RepeatingKeyHandlerState<S, #(#tys),*> {
+                    fn release(&self, event: ::core::option::Option<&::web_sys::KeyboardEvent>) -> bool {
+                        if !self.is_pressed.replace(false) {
+                            return true;
+                        }
+                        self.generation.set(self.generation.get().wrapping_add(1));
+                        self.initial_event.replace(::core::option::Option::None);
+                        (self.keyup)(&self.state, event)
+                    }
+
+                    fn schedule(this: &::std::rc::Rc<Self>, timeout: i32) {
+                        let generation = this.generation.get();
+                        let weak = ::std::rc::Rc::downgrade(this);
+                        let callback = ::wasm_bindgen::closure::Closure::once_into_js(move || {
+                            let this = match weak.upgrade() {
+                                ::core::option::Option::Some(this) => this,
+                                // The key handler is already dropped
+                                ::core::option::Option::None => return,
+                            };
+                            if this.generation.get() != generation {
+                                return;
+                            }
+                            // `keyup` doesn't arrive when the document loses focus mid-press
+                            let has_focus = ::web_sys::window()
+                                .and_then(|window| window.document())
+                                .map_or(true, |document| document.has_focus().unwrap_or(true));
+                            if !has_focus {
+                                let _ = this.release(::core::option::Option::None);
+                                return;
+                            }
+                            let initial_event = this.initial_event.borrow().clone();
+                            let _ = (this.keydown)(&this.state, initial_event.as_ref());
+                            Self::schedule(&this, this.interval);
+                        });
+                        if let ::core::option::Option::Some(window) = ::web_sys::window() {
+                            let _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(
+                                ::wasm_bindgen::JsCast::unchecked_ref(&callback),
+                                timeout,
+                            );
+                        }
+                    }
+                }
+
+                let __state = #state;
+                #(let #events = #key_event_handlers;)*
+                let __inner = ::std::rc::Rc::new(RepeatingKeyHandlerState {
+                    is_pressed: ::core::cell::Cell::new(false),
+                    generation: ::core::cell::Cell::new(0),
+                    initial_event: ::core::cell::RefCell::new(::core::option::Option::None),
+                    delay: (#delay) as i32,
+                    interval: (#interval) as i32,
+                    state: __state,
+                    #(#events,)*
+                });
+                let keydown = constrain(&__inner, |inner, event| {
+                    let is_pressed = &inner.is_pressed;
+                    // The repetitions come from the timer rather than from the OS
+                    if !#is_new_press {
+                        return true;
+                    }
+                    // Stops the repetitions of the press whose `keyup` was lost
+                    inner.generation.set(inner.generation.get().wrapping_add(1));
+                    if !(inner.keydown)(&inner.state, event) {
+                        // The declined press neither repeats nor receives `keyup`
+                        is_pressed.set(false);
+                        return false;
+                    }
+                    inner.initial_event.replace(event.cloned());
+                    RepeatingKeyHandlerState::schedule(inner, inner.delay);
+                    true
+                });
+                let keyup = constrain(&__inner, |inner, event| inner.release(event));
+                #keypress_handler
+                #key_handler
+            }
         ));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(args: &str) -> String {
+        match syn::parse_str::<Args>(args) {
+            Ok(_) => panic!("`{}` is expected to be rejected", args),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn handler(args: &str, event_id: usize) -> KeyEventHandler {
+        let Args {
+            key_event_handlers, ..
+        } = syn::parse_str::<Args>(args).unwrap();
+        key_event_handlers.into_iter().nth(event_id).unwrap()
+    }
+
+    #[test]
+    fn arguments_can_be_given_in_any_order() {
+        let args = syn::parse_str::<Args>(
+            "KEY_A, keyup = { |_| () }, strategy = both, state = 0, keydown = { |_| () },",
+        )
+        .unwrap();
+        let state = &args.state;
+        assert_eq!(quote!(#state).to_string(), "0");
+        assert!(args.strategy.unwrap().kind == StrategyKind::Both);
+    }
+
+    #[test]
+    fn omitted_arguments_have_defaults() {
+        let args = syn::parse_str::<Args>("KEY_A").unwrap();
+        let state = &args.state;
+        assert_eq!(quote!(#state).to_string(), "()");
+        assert!(args.repeat.is_none() && args.strategy.is_none());
+        let callable = &args.key_event_handlers[KEYUP_ID].callable;
+        assert_eq!(quote!(#callable).to_string(), "| _ : & _ | { }");
+    }
+
+    #[test]
+    fn duplicate_and_unknown_arguments_are_rejected() {
+        assert_eq!(
+            parse_error("KEY_A, state = 0, keydown = { |_| () }, state = 1"),
+            "argument `state` is specified more than once"
+        );
+        assert!(parse_error("KEY_A, stat = 0").starts_with("unknown argument `stat`"));
+    }
+
+    #[test]
+    fn any_callable_expression_is_an_event_handler() {
+        let path = handler("KEY_A, keydown = { Player::on_jump }", KEYDOWN_ID);
+        assert!(path.prelude.is_empty());
+        assert!(path.closure().is_none() && !path.takes_event());
+        let call = handler("KEY_A, keydown = { make_handler(1) }", KEYDOWN_ID);
+        assert!(call.closure().is_none());
+        let closure = handler(
+            "KEY_A, keydown = { let x = 1; move |state, event| () }",
+            KEYDOWN_ID,
+        );
+        assert_eq!(closure.prelude.len(), 1);
+        assert!(closure.takes_event() && !closure.is_async());
+    }
+
+    #[test]
+    fn callable_with_trailing_semicolon_is_rejected() {
+        assert_eq!(
+            parse_error("KEY_A, keyup = { on_land; }"),
+            "last statement of `keyup` event handler is expected to be a callable expression \
+            without a trailing semicolon"
+        );
+    }
+
+    #[test]
+    fn key_handlers_keep_the_key_code_in_their_type() {
+        let args = "KEY_A, keydown = { |state, event| () }, keyup = { on_land }";
+        let mut primitive = proc_macro2::TokenStream::new();
+        syn::parse_str::<Args>(args)
+            .unwrap()
+            .extend_with_primitive_key_handler_expr(&mut primitive);
+        let mut simplified = proc_macro2::TokenStream::new();
+        syn::parse_str::<Args>(&format!("{}, repeat = {{ delay = 1, interval = 1 }}", args))
+            .unwrap()
+            .extend_with_simplified_key_handler_expr(&mut simplified);
+        for expansion in [primitive.to_string(), simplified.to_string()] {
+            assert!(expansion.contains(
+                ":: wasm_keyboard :: implementors :: KeyHandler :: < { :: wasm_keyboard :: \
+                uievents_code :: KeyboardEventCode :: KeyA as u8 }"
+            ));
+            // Without the event, the handler receives `None` rather than a synthetic event
+            assert!(!expansion.contains("KeyboardEventInit"));
+        }
+    }
+
+    #[test]
+    fn async_event_handlers_are_checked() {
+        assert!(handler("KEY_A, keydown = { async move |state| () }", KEYDOWN_ID).is_async());
+        assert_eq!(
+            parse_error("KEY_A, keydown = { async move |state, event| () }"),
+            "async `keydown` event handler must accept only the state"
+        );
+        assert_eq!(
+            parse_error("KEY_A, keydown = { |_| () }, async_policy = queue"),
+            "`async_policy` argument requires an async event handler"
+        );
+    }
+
+    #[test]
+    fn async_event_handlers_receive_the_state_and_the_captures_by_value() {
+        for keydown in [
+            "async move |state| { state.set(1); }",
+            "let client = client.clone(); async move |state: Rc<Cell<u8>>| { client.len(); }",
+        ] {
+            let keydown = handler(&format!("KEY_A, keydown = {{ {} }}", keydown), KEYDOWN_ID);
+            let mut block = proc_macro2::TokenStream::new();
+            keydown.extend_with_block(&mut block, &quote!(&__state));
+            let block = block.to_string();
+            // The state tells the type of the parameter of the closure
+            assert!(block.contains("spawn_local_on_call (& __state , move |"));
+            assert!(block.contains("| async move {"));
+        }
+        let mut items = proc_macro2::TokenStream::new();
+        AsyncPolicy::extend_with_spawn_local_on_call_fn(None, &mut items);
+        let items = items.to_string();
+        // Each call clones the closure along with the variables that it captured
+        assert!(items.contains("F : FnOnce (S) -> FUTURE + :: core :: clone :: Clone"));
+        assert!(items.contains("f . clone () (state . clone ())"));
+    }
+
+    fn simplified_expansion(args: &str) -> String {
+        let mut ts = proc_macro2::TokenStream::new();
+        syn::parse_str::<Args>(args)
+            .unwrap()
+            .extend_with_simplified_key_handler_expr(&mut ts);
+        ts.to_string()
+    }
+
+    #[test]
+    fn repeat_argument_is_parsed() {
+        let args = syn::parse_str::<Args>("KEY_A, repeat = { delay = 500, interval = 1000 / 30, }")
+            .unwrap();
+        let Repeat {
+            name,
+            delay,
+            interval,
+        } = args.repeat.as_ref().unwrap();
+        assert_eq!(quote!(#delay).to_string(), "500");
+        assert_eq!(quote!(#interval).to_string(), "1000 / 30");
+        assert_eq!(args.simplified_only_argument(), Some(name));
+        assert_eq!(
+            parse_error("KEY_A, repeat = { interval = 1, delay = 1 }"),
+            "expected `delay`"
+        );
+        assert_eq!(
+            parse_error("KEY_A, repeat = { delay = 1, interval = 1, period = 1 }"),
+            "unexpected token"
+        );
+        assert_eq!(
+            parse_error(
+                "KEY_A, repeat = { delay = 1, interval = 1 }, repeat = { delay = 2, interval = 2 }"
+            ),
+            "argument `repeat` is specified more than once"
+        );
+    }
+
+    #[test]
+    fn repeat_argument_drives_keydown_from_a_timer() {
+        let expansion = simplified_expansion(
+            "KEY_A, keydown = { |_| () }, repeat = { delay = 500, interval = 50 }",
+        );
+        assert!(expansion.contains("struct RepeatingKeyHandlerState"));
+        assert!(expansion.contains("delay : (500) as i32 , interval : (50) as i32"));
+        assert!(expansion.contains("RepeatingKeyHandlerState :: schedule (inner , inner . delay)"));
+        // The repeating handler releases the key when the document loses focus mid-press
+        assert!(expansion.contains("has_focus"));
+        assert!(!simplified_expansion("KEY_A, keydown = { |_| () }").contains("schedule"));
+    }
+
+    fn new_press_check(strategy: Option<&Strategy>) -> String {
+        let mut ts = proc_macro2::TokenStream::new();
+        Strategy::extend_with_new_press_check(
+            strategy,
+            &mut ts,
+            &quote::format_ident!("is_pressed"),
+            &quote::format_ident!("event"),
+        );
+        ts.to_string()
+    }
+
+    #[test]
+    fn strategy_argument_is_parsed() {
+        for (value, kind) in [
+            ("cell", StrategyKind::Cell),
+            ("repeat", StrategyKind::Repeat),
+            ("both", StrategyKind::Both),
+        ] {
+            let args = syn::parse_str::<Args>(&format!("KEY_A, strategy = {}", value)).unwrap();
+            let strategy = args.strategy.as_ref().unwrap();
+            assert!(strategy.kind == kind);
+            assert_eq!(args.simplified_only_argument(), Some(&strategy.name));
+        }
+        assert_eq!(
+            parse_error("KEY_A, strategy = timer"),
+            "unknown strategy `timer`, expected one of `cell`, `repeat` or `both`"
+        );
+        assert_eq!(
+            parse_error("KEY_A, strategy = cell, strategy = both"),
+            "argument `strategy` is specified more than once"
+        );
+    }
+
+    #[test]
+    fn strategy_tells_new_presses_apart_from_repeats() {
+        let [cell, repeat, both] = ["cell", "repeat", "both"].map(|value| {
+            let args = syn::parse_str::<Args>(&format!("KEY_A, strategy = {}", value)).unwrap();
+            new_press_check(args.strategy.as_ref())
+        });
+        // The cell strategy is the default one
+        assert_eq!(cell, new_press_check(None));
+        assert_eq!(cell, "! is_pressed . replace (true)");
+        assert!(repeat.contains("! event . map (| event | event . repeat ()) . unwrap_or (false)"));
+        // Without the event, the both strategy falls back to the cell
+        assert!(both.contains(":: core :: option :: Option :: None => ! __was_pressed"));
+        for args in [
+            "KEY_A, strategy = repeat",
+            "KEY_A, strategy = repeat, repeat = { delay = 1, interval = 1 }",
+        ] {
+            assert!(simplified_expansion(args).contains("event . repeat ()"));
+        }
+    }
+}
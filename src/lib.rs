@@ -2,7 +2,30 @@
 
 use proc_macro::TokenStream;
 
+/// Defines items that the expansions of the macros need at runtime.
+///
+/// The items themselves are compiled only for the unit tests of this crate, while
+/// `$tokens_fn` returns their tokens for the expansions, so both share the same source.
+/// The items must not use `#` interpolation.
+macro_rules! runtime_items {
+    ($tokens_fn:ident => $($item:item)*) => {
+        $(
+            #[cfg(test)]
+            $item
+        )*
+
+        pub(crate) fn $tokens_fn() -> proc_macro2::TokenStream {
+            quote::quote!($($item)*)
+        }
+    };
+}
+
+mod key_code;
 mod key_handlers;
+mod long_press_handler;
+mod modifiers;
+mod multi_tap_handler;
+mod sequence_handler;
 mod start_keyboard_handler;
 
 use start_keyboard_handler::Args;
@@ -21,6 +44,30 @@ pub(crate) const KEYUP_ID: usize = 1;
 #[cfg(feature = "keypress")]
 pub(crate) const KEYPRESS_ID: usize = 2;
 
+macro_rules! key_identifiers_doc {
+    () => {
+r#"# Key identifiers
+
+All macros of this crate accept key identifiers in either of the following forms:
+
+* a path to a variant of [`KeyboardEventCode`](https://docs.rs/uievents-code/latest/uievents_code/enum.KeyboardEventCode.html),
+e.g. `KeyboardEventCode::KeyA`;
+* a path to a `&'static str` constant from [`uievents_code`](https://docs.rs/uievents-code/latest/uievents_code/),
+e.g. [`KEY_A`](https://docs.rs/uievents-code/latest/uievents_code/writing_system/constant.KEY_A.html);
+* a string literal with the value of [`KeyboardEvent.code`](https://developer.mozilla.org/en-US/docs/Web/API/KeyboardEvent/code),
+e.g. `"KeyA"`.
+
+Paths are resolved by their last segment, so they don't have to be in scope. A path whose last
+segment is not a known key code is emitted unchanged, as a `&'static str` constant in
+[`start_keywise_keyboard_handler!`] and as a `KeyboardEventCode` constant elsewhere, so it can
+refer to a constant of your own. Such paths can't bound key ranges or be bound alongside them.
+Unknown key codes in string literals and in paths to variants, such as
+`KeyboardEventCode::KeyWW`, are reported at compile time, with a suggestion of a similar
+known key code.
+"#
+    };
+}
+
 macro_rules! arguments_doc {
     () => {
 r#"# Macro input
@@ -29,19 +76,22 @@ Both [`new_primitive_key_handler!`] and [`new_simplified_key_handler!`] expect
 the same [token stream](https://doc.rust-lang.org/reference/procedural-macros.html#function-like-procedural-macros)
 structure — a comma-separated list of arguments adhering to the rules below.
 
-The first argument must be a [path](https://docs.rs/syn/latest/syn/struct.Path.html)
-corresponding to a constant of type [`KeyboardEventCode`](https://docs.rs/uievents-code/latest/uievents_code/enum.KeyboardEventCode.html),
-e.g. `KeyboardEventCode::KeyA`.
+The first argument must be a [key identifier](#key-identifiers), e.g. `KeyboardEventCode::KeyA`.
+The rest are named arguments that can be given in any order and each at most once.
 
-The second argument must be a `state = `-prefixed [expression](https://docs.rs/syn/latest/syn/enum.Expr.html)
-that should evaluate to the initial state of the key handler, e.g. `state = ()`.
+The `state = `-prefixed [expression](https://docs.rs/syn/latest/syn/enum.Expr.html)
+should evaluate to the initial state of the key handler, e.g. `state = Rc::new(Cell::new(0))`.
+When omitted, the state is `()`.
 
-Each of the arguments 3,4 and, with `keypress` feature, 5 must be a `<keydown|keyup|keypress> = `-prefixed
-[block](https://docs.rs/syn/latest/syn/struct.Block.html) where the last
-[statement](https://docs.rs/syn/latest/syn/enum.Stmt.html) must be a
-[closure expression](https://docs.rs/syn/latest/syn/struct.ExprClosure.html). Extra
-statements before the closure are allowed in order to write some closure prelude code. For example,
-the following code snippet is a valid input for the third (but not fourth) argument:
+Each of the `keydown`, `keyup` and, with `keypress` feature, `keypress` event handlers is a
+`<keydown|keyup|keypress> = `-prefixed [block](https://docs.rs/syn/latest/syn/struct.Block.html)
+where the last [statement](https://docs.rs/syn/latest/syn/enum.Stmt.html) must be an
+[expression](https://docs.rs/syn/latest/syn/enum.Expr.html) without a trailing semicolon
+that evaluates to an [`Fn(&State)`](Fn) callable, e.g. a closure, a path to a function
+such as `keydown = { Self::on_jump }` or a variable holding a closure such as
+`keydown = { on_jump }`. Extra statements before the callable are allowed in order to write
+some prelude code. When omitted, the event handler does nothing. For example,
+the following code snippet is a valid `keydown` event handler:
 
 ```rust,ignore
 keydown = {
@@ -55,13 +105,53 @@ keydown = {
 },
 ```
 
-## Notes
+The callable receives a reference to the state. A closure expression can additionally
+receive the keyboard event, e.g. `move |state, event| ..`. In this case, `event` is
+an `Option<&web_sys::KeyboardEvent>`: the event that is being dispatched when the key handler
+is driven by [`start_keywise_keyboard_handler!`], or the initial `keydown` on
+[auto-repeat](#auto-repeat). Otherwise, e.g. when the methods of
+[`wasm_keyboard::KeyHandler`](https://docs.rs/wasm_keyboard/latest/wasm_keyboard/trait.KeyHandler.html)
+are called directly, outside of `wasm32` targets or on the synthetic release of the keys after
+the page loses focus, it is `None`. For example, the handler can read `time_stamp()` or
+`location()` of the event or call `prevent_default()` on it.
 
-Notice that unlike [`start_keywise_keyboard_handler!`], both [`new_primitive_key_handler!`] and
-[`new_simplified_key_handler!`] expect a path of type [`KeyboardEventCode`](https://docs.rs/uievents-code/latest/uievents_code/enum.KeyboardEventCode.html)
-(such as `KeyboardEventCode::KeyA`) and not a path of type `&'static str` (such as 
-[`KEY_A`](https://docs.rs/uievents-code/latest/uievents_code/writing_system/constant.KEY_A.html)).
+The key handler is a [`wasm_keyboard::implementors::KeyHandler`](https://docs.rs/wasm_keyboard/latest/wasm_keyboard/implementors/struct.KeyHandler.html),
+whose methods take no event, so [`start_keywise_keyboard_handler!`] hands the event over
+through a property of the JS global object, which requires `web-sys` 0.3.70 or newer for its
+re-export of `js-sys`.
 
+The callable can return `()`, a `bool` or a
+[`ControlFlow<()>`](https://doc.rust-lang.org/core/ops/enum.ControlFlow.html) to tell whether
+it handled the event, which lets [`start_keywise_keyboard_handler!`] pass the declined events
+on to other bindings (see its [Declined events](start_keywise_keyboard_handler!#declined-events)).
+Async event handlers always handle the event.
+
+A closure expression can also be async, e.g. `async move |state| ..`, in which case the macro spawns its
+future with [`wasm_bindgen_futures::spawn_local`](https://docs.rs/wasm-bindgen-futures/latest/wasm_bindgen_futures/fn.spawn_local.html),
+so the crate has to depend on `wasm-bindgen-futures`. Since the future outlives the call,
+the async closure receives a clone of the state (which, hence, has to implement [`Clone`],
+e.g. by being an [`Rc`](std::rc::Rc)) rather than a reference to it, and it cannot receive
+the keyboard event. Likewise, the future of each call owns clones of the variables that
+the closure captures, so they have to implement [`Clone`] too. The optional `async_policy = <drop|queue|concurrent>` argument
+tells what happens when the handler is called while the future of its previous call is still
+running:
+
+* `drop` ignores the call;
+* `queue` runs the future of the call after the futures of the previous calls complete;
+* `concurrent` (the default) runs the future of the call right away.
+
+```rust,ignore
+keydown = {
+    async move |state| {
+        let response = state.client.fetch_inventory().await;
+        state.inventory.replace(response);
+    }
+},
+```
+
+Additionally, [`new_simplified_key_handler!`] accepts optional arguments
+`repeat = { delay = <expr>, interval = <expr> }` (see [Auto-repeat](#auto-repeat)) and
+`strategy = <cell|repeat|both>` (see [Strategies](#strategies)).
 "#
     };
 }
@@ -118,6 +208,8 @@ fn main() -> Result<(), JsValue> {
     );
 
     start_keywise_keyboard_handler!(kh: Kh, document, [KEY_W => w_handler]);
+    // Keep the listeners attached for the lifetime of the page
+    kh.forget();
 
     // Manufacture the element we're gonna append
     let val = document.create_element("p")?;
@@ -138,25 +230,323 @@ fn main() -> Result<(), JsValue> {
 /// for the "keywise keyboard handler", creates an instance of the parameterized type, and
 /// adds event listeners to the target, which often will be an instance of [`web_sys::Document`].
 ///
+/// The target can be any expression that evaluates to [`web_sys::EventTarget`] or to anything
+/// that dereferences to it, such as [`web_sys::Window`], [`web_sys::Document`] or
+/// [`web_sys::HtmlElement`], e.g. `web_sys::window().unwrap()`. The expression is evaluated once.
+///
 /// Keywise keyboard handler is a keyboard handler that is created from a list of key handlers,
 /// each with its own state.
 ///
 /// Individual key handlers can be created using [`new_primitive_key_handler!`] or[`new_simplified_key_handler!`].
 ///
+/// # Observers
+///
+/// Besides key handlers, the list of bindings can contain observers, i.e. `.. => handler`
+/// bindings, where the handler is any closure accepting `&web_sys::KeyboardEvent`, such as
+/// the one created by [`new_sequence_handler!`]. Observers are called for every keyboard event
+/// before it is dispatched to the key handlers.
+///
+/// # Fallback
+///
+/// The list of bindings can also contain a single `_ => handler` binding, where the handler is
+/// any closure accepting the [`KeyboardEvent.code`] as `&str`, e.g. `"KeyA"`. It is called on
+/// the initial [`keydown`] of every key that matches no binding (including the keys that are bound
+/// only with other modifiers), which is useful for "press any key" screens and analytics.
+/// Keys pressed while a [prefix](#prefixes) is pending are not passed to the fallback handler.
+///
+/// ```rust,ignore
+/// start_keywise_keyboard_handler!(
+///     kh: Kh,
+///     document,
+///     [KEY_W => w_handler, _ => move |code| log_unbound_key(code)]
+/// );
+/// ```
+///
+/// # Modifiers
+///
+/// A key in the list of bindings can be prefixed with `+`-terminated modifiers, e.g.
+/// `Ctrl+Shift+KEY_Z => redo_handler`. The supported modifiers are `Ctrl`, `Shift`, `Alt`
+/// and `Meta`, which correspond to [`KeyboardEvent.ctrlKey`], [`KeyboardEvent.shiftKey`],
+/// [`KeyboardEvent.altKey`] and [`KeyboardEvent.metaKey`] respectively.
+///
+/// Additionally, the platform-neutral `Mod` modifier is resolved at runtime to `Meta` (i.e. Cmd)
+/// on Apple platforms and to `Ctrl` elsewhere, e.g. `Mod+KEY_S => save_handler`. Since
+/// `Mod+KEY_S` and `Ctrl+KEY_S` (or `Meta+KEY_S`) would be the same binding on some platforms,
+/// binding a key with both is a compile-time error. For the same reason, `Mod` cannot be
+/// combined with `Ctrl` or `Meta` in one binding.
+///
+/// * A binding with modifiers matches only when exactly these modifiers are held.
+/// * A binding with `Any` among its modifiers, e.g. `Ctrl+Any+KEY_S`, matches when
+///   the listed modifiers are held, regardless of the other ones.
+/// * A binding without modifiers matches regardless of the modifiers that are held.
+///
+/// When several bindings of the same key match, the one with more modifiers takes precedence,
+/// and a binding that matches exactly takes precedence over a binding with `Any`. While the key
+/// is held, all its events (including `keyup`) are routed to the key handler that received
+/// the initial `keydown`, even if the modifiers are pressed or released in the meantime.
+///
+/// By default, the platform is derived from [`Navigator.platform`] of the global `window` at
+/// start-up. It can also be passed in with the `platform = ` option following the list of
+/// bindings, where the value can be of any type implementing [`AsRef<str>`], e.g. a fake
+/// platform in tests:
+///
+/// ```rust,ignore
+/// start_keywise_keyboard_handler!(
+///     kh: Kh,
+///     document,
+///     [Mod+KEY_S => save_handler],
+///     platform = "MacIntel",
+/// );
+/// ```
+///
+/// Each key with a given set of modifiers can be bound at most once. Duplicate bindings,
+/// which would otherwise be silently unreachable, are reported at compile time.
+///
+/// # Alternation
+///
+/// Several keys (each possibly with modifiers) can share one key handler and, thereby, one
+/// state when they are separated with `|`, e.g. `KEY_W | ARROW_UP => up_handler`.
+///
+/// The key handler considers itself pressed while any of the alternates is held: it receives
+/// `keydown` for each of them, but `keyup` only once the last held alternate is released.
+/// Therefore, with the default strategy of [`new_simplified_key_handler!`], pressing `ARROW_UP`
+/// while `KEY_W` is held doesn't trigger the handler again.
+///
+/// Prefixes cannot have alternates.
+///
+/// # Key ranges and groups
+///
+/// Instead of a single key, a binding can use
+///
+/// * an inclusive range of keys, e.g. `Digit0..=Digit9` or `F1..=F12`, which follows the order
+///   of the variants of [`KeyboardEventCode`];
+/// * a group of keys, i.e. the beginning of their names followed by `*`, e.g. `Numpad*`,
+///   which matches every key whose variant of [`KeyboardEventCode`] or `&'static str` constant
+///   starts with it.
+///
+/// Ranges and groups can have modifiers and be combined with [alternates](#alternation), e.g.
+/// `Ctrl+Digit0..=Digit9 | Ctrl+Numpad* => select_handler`.
+///
+/// Such a binding can take a key handler, which is then shared by the keys like with
+/// [alternates](#alternation), e.g. `Numpad* => numpad_handler`. Since the methods of a key
+/// handler don't tell which key is pressed, the binding can instead take a closure that receives
+/// the [`KeyboardEventCode`] of the pressed key. The closure is called once per press, on
+/// the initial `keydown`, and never on `keyup`:
+///
+/// ```rust,ignore
+/// start_keywise_keyboard_handler!(
+///     kh: Kh,
+///     document,
+///     [Digit1..=Digit9 => move |code| hotbar.select(code as u8 - KeyboardEventCode::Digit1 as u8)],
+/// );
+/// ```
+///
+/// Only a closure expression written in the binding receives the code. Any other expression,
+/// e.g. a variable holding a closure, is taken for a key handler. A binding without key ranges
+/// and groups cannot take such a closure, while all keys of a binding that does, including its
+/// single-key alternates, have to be known key codes. Prefixes cannot be key ranges or groups.
+///
+/// The closure can return `()`, a `bool` or a [`ControlFlow<()>`] to tell whether it handled
+/// the press (see [Declined events](#declined-events)). The return types implement
+/// `<TypeName>Outcome` trait (e.g. `KhOutcome`) declared by the macro.
+///
+/// ```rust,ignore
+/// start_keywise_keyboard_handler!(
+///     kh: Kh,
+///     document,
+///     [
+///         // Only the slots that are unlocked are selected with Shift
+///         Shift+Digit1..=Digit9 => move |code| hotbar.select_if_unlocked(code),
+///         DIGIT1 => first_slot_handler,
+///     ],
+/// );
+/// ```
+///
+/// # Prefixes
+///
+/// A key (possibly with modifiers) can be bound to a bracketed list of bindings instead of
+/// a key handler, which makes it a prefix of an Emacs-style multi-stroke shortcut, e.g.
+/// `Ctrl+KEY_X => [Ctrl+KEY_S => save_handler, KEY_K => kill_handler]` for `C-x C-s`
+/// and `C-x k`. Pressing the prefix makes it pending, and the next pressed key (along with
+/// the modifiers that are held) is dispatched only to the bindings that follow the prefix.
+/// Prefixes cannot be nested.
+///
+/// While a prefix is pending,
+///
+/// * pressing a modifier key (e.g. `ControlLeft`) keeps it pending, so that the modifiers
+///   of the next chord can be pressed;
+/// * pressing `Escape` (unless it is bound after the prefix) cancels it;
+/// * pressing any other key that doesn't match a binding after the prefix is reported as
+///   an invalid continuation;
+/// * after `prefix_timeout` milliseconds, if the option is given, the prefix is abandoned.
+///
+/// The prefix is reported with the following options, which are closures accepting
+/// the description of the prefix, e.g. `"Ctrl+KeyX"`:
+///
+/// * `on_pending = |prefix: Option<&str>| ..`, which is called with `Some(prefix)` when
+///   the prefix becomes pending and with `None` when it stops being pending, e.g. in order to
+///   show and hide a "which-key" hint;
+/// * `on_invalid = |prefix: &str, code: Option<&str>| ..`, which is called with the
+///   [`KeyboardEvent.code`] of the key that doesn't continue the prefix, or with `None` if
+///   the prefix was cancelled or abandoned without a continuation.
+///
+/// ```rust,ignore
+/// start_keywise_keyboard_handler!(
+///     kh: Kh,
+///     document,
+///     [Ctrl+KEY_X => [Ctrl+KEY_S => save_handler, KEY_K => kill_handler]],
+///     prefix_timeout = 2000,
+///     on_pending = move |prefix| hint.set_text_content(prefix.map(|prefix| prefix.to_owned() + "-")),
+///     on_invalid = |prefix, code| log::warn!("{prefix} {code:?} is undefined"),
+/// );
+/// ```
+///
+/// # Default actions and propagation
+///
+/// By default, the keyboard events are left to the browser, e.g. `Space` still scrolls the page
+/// and `Ctrl+S` still opens the save dialog. A key handler or a prefix binding can be preceded
+/// with `#[prevent_default]` and `#[stop_propagation]` attributes, which make the keywise
+/// keyboard handler call [`Event.preventDefault()`] and [`Event.stopPropagation()`]
+/// respectively for every event of the key that matches the binding.
+///
+/// The handler-wide defaults can be set with `prevent_default = ` and `stop_propagation = `
+/// options, whose values are `bool` literals, and overridden per binding, e.g.
+/// `#[prevent_default = false]`. Keys that match no binding are always left to the browser.
+///
+/// ```rust,ignore
+/// start_keywise_keyboard_handler!(
+///     kh: Kh,
+///     document,
+///     [
+///         SPACE => jump_handler,
+///         #[stop_propagation] Mod+KEY_S => save_handler,
+///         #[prevent_default = false] F5 => refresh_handler,
+///     ],
+///     prevent_default = true,
+/// );
+/// ```
+///
+/// # Declined events
+///
+/// A binding can decline the events routed to it. The closures of [key range and group
+/// bindings](#key-ranges-and-groups) and the event handlers of the key handlers created by
+/// [`new_primitive_key_handler!`] and [`new_simplified_key_handler!`] tell whether they handled
+/// the event with their return values: `()`, `true` and `ControlFlow::Break(())` mean that
+/// they did, while `false` and `ControlFlow::Continue(())` mean that they declined the event.
+/// Other key handlers, whose methods return nothing, always handle the events routed to them.
+///
+/// A declined event isn't [consumed](#default-actions-and-propagation). A declined initial
+/// `keydown` also falls through to the matching bindings of the same key with lower precedence,
+/// if any, or to the [fallback handler](#fallback) otherwise, and the rest of the events of
+/// the press are routed to the binding that handled it. A key handler created by
+/// [`new_simplified_key_handler!`] treats the press that it declined as released.
+///
+/// ```rust,ignore
+/// let dash_handler = new_simplified_key_handler!(
+///     SPACE,
+///     state = player.clone(),
+///     // In the menu, `Shift+Space` is left to the binding below
+///     keydown = { move |player| player.borrow_mut().try_dash() },
+///     keyup = { move |_player| {} },
+/// );
+///
+/// start_keywise_keyboard_handler!(
+///     kh: Kh,
+///     document,
+///     [Shift+SPACE => dash_handler, SPACE => menu_handler],
+///     prevent_default = true,
+/// );
+/// ```
+///
+/// # Listener options
+///
+/// The `capture = `, `passive = ` and `once = ` options, whose values are `bool` expressions
+/// evaluated once at start-up, are passed to the keyboard event listeners through
+/// [`AddEventListenerOptions`], which requires the `AddEventListenerOptions` feature of
+/// `web_sys`. For example, listening in the capture phase intercepts the keys before
+/// the descendants of the target (such as third-party widgets) receive them:
+///
+/// ```rust,ignore
+/// start_keywise_keyboard_handler!(kh: Kh, document, [KEY_W => w_handler], capture = true);
+/// ```
+///
+/// Note that passive listeners cannot [prevent default actions](#default-actions-and-propagation).
+///
+/// # Lost key releases
+///
+/// When the page loses focus while a key is held, the `keyup` event of the key never arrives,
+/// so the key handler would consider the key held forever. Hence, the keywise keyboard handler
+/// also listens for [`blur`] and [`visibilitychange`] events on the global `window`, which
+/// requires `Window` feature of `web_sys`. On these events, it sends a synthetic release,
+/// i.e. calls `handle_keyup`, to every key handler whose key is currently held and abandons
+/// the pending prefix, if any. Without the global `window`, e.g. in a worker, these events
+/// are not listened for.
+///
+/// The `blur_target = ` option replaces the global `window` with another value dereferencing
+/// to [`web_sys::EventTarget`]. Since `blur` events don't bubble, the target receives only
+/// its own ones:
+///
+/// * the global `window` receives both `blur` of the window and `visibilitychange` of
+///   the document, which bubbles to it;
+/// * a `Document` receives only `visibilitychange`;
+/// * an element receives only `blur` of the element itself, which suits the keywise keyboard
+///   handlers attached to a focusable element, e.g. a `<canvas>` with `tabindex`.
+///
+/// ```rust,ignore
+/// let canvas: web_sys::HtmlCanvasElement = get_canvas();
+/// start_keywise_keyboard_handler!(kh: Kh, canvas, [KEY_W => w_handler], blur_target = canvas);
+/// ```
+///
+/// The same release can be triggered manually with `release_pressed_keys(&self)` method
+/// of the keywise keyboard handler.
+///
+/// # Teardown
+///
+/// The macro binds the given instance name (e.g. `kh`) to a guard of type `<TypeName>Guard`
+/// (e.g. `KhGuard`) that owns the event listeners. When the guard is dropped, the listeners
+/// are removed from the target, so the lifetime of the keyboard handler follows Rust ownership.
+/// The guard dereferences to the keywise keyboard handler and offers two methods:
+///
+/// * `detach(self)`, which removes the event listeners explicitly;
+/// * `forget(self)`, which keeps the event listeners attached for the rest of the lifetime
+///   of the page.
+///
+/// # Fallible start-up
+///
+/// By default, the macro panics if an event listener cannot be added to the target.
+/// If the instance name is prefixed with the `try` keyword, the instance is bound to
+/// `Result<<TypeName>Guard, JsValue>` instead, and the listeners that were already added
+/// are removed when a later one fails:
+///
+/// ```rust,ignore
+/// start_keywise_keyboard_handler!(try kh: Kh, document, [KEY_W => w_handler]);
+/// let kh = kh?;
+/// ```
+///
 /// # Example
 ///
 #[doc = simplified_key_handling_example_doc!()]
 ///
-/// ## Notes
-/// 
-/// Notice that even though both [`new_primitive_key_handler!`] and
-/// [`new_simplified_key_handler!`] expect a path of type [`KeyboardEventCode`](https://docs.rs/uievents-code/latest/uievents_code/enum.KeyboardEventCode.html)
-/// (such as 
-/// [`KeyboardEventCode::KeyA`](https://docs.rs/uievents-code/latest/uievents_code/enum.KeyboardEventCode.html#variant.KeyA)),
-/// [`start_keywise_keyboard_handler!`] expects a path of type `&'static str` (such as 
-/// [`KEY_A`](https://docs.rs/uievents-code/latest/uievents_code/writing_system/constant.KEY_A.html)).
+#[doc = key_identifiers_doc!()]
 ///
 /// [`web_sys::Document`]: https://docs.rs/web-sys/latest/web_sys/struct.Document.html
+/// [`web_sys::Window`]: https://docs.rs/web-sys/latest/web_sys/struct.Window.html
+/// [`web_sys::HtmlElement`]: https://docs.rs/web-sys/latest/web_sys/struct.HtmlElement.html
+/// [`KeyboardEvent.ctrlKey`]: https://developer.mozilla.org/en-US/docs/Web/API/KeyboardEvent/ctrlKey
+/// [`KeyboardEvent.shiftKey`]: https://developer.mozilla.org/en-US/docs/Web/API/KeyboardEvent/shiftKey
+/// [`KeyboardEvent.altKey`]: https://developer.mozilla.org/en-US/docs/Web/API/KeyboardEvent/altKey
+/// [`KeyboardEvent.metaKey`]: https://developer.mozilla.org/en-US/docs/Web/API/KeyboardEvent/metaKey
+/// [`KeyboardEvent.code`]: https://developer.mozilla.org/en-US/docs/Web/API/KeyboardEvent/code
+/// [`KeyboardEventCode`]: https://docs.rs/uievents-code/latest/uievents_code/enum.KeyboardEventCode.html
+/// [`ControlFlow<()>`]: https://doc.rust-lang.org/core/ops/enum.ControlFlow.html
+/// [`Navigator.platform`]: https://developer.mozilla.org/en-US/docs/Web/API/Navigator/platform
+/// [`web_sys::EventTarget`]: https://docs.rs/web-sys/latest/web_sys/struct.EventTarget.html
+/// [`keydown`]: https://developer.mozilla.org/en-US/docs/Web/API/Element/keydown_event
+/// [`Event.preventDefault()`]: https://developer.mozilla.org/en-US/docs/Web/API/Event/preventDefault
+/// [`Event.stopPropagation()`]: https://developer.mozilla.org/en-US/docs/Web/API/Event/stopPropagation
+/// [`AddEventListenerOptions`]: https://docs.rs/web-sys/latest/web_sys/struct.AddEventListenerOptions.html
+/// [`blur`]: https://developer.mozilla.org/en-US/docs/Web/API/Window/blur_event
+/// [`visibilitychange`]: https://developer.mozilla.org/en-US/docs/Web/API/Document/visibilitychange_event
 /// [variadic generics]: https://github.com/rust-lang/rust/issues/10124
 #[proc_macro]
 pub fn start_keywise_keyboard_handler(input: TokenStream) -> TokenStream {
@@ -227,6 +617,8 @@ pub fn start_keywise_keyboard_handler(input: TokenStream) -> TokenStream {
 ///     );
 ///
 ///     start_keywise_keyboard_handler!(kh: Kh, document, [KEY_W => w_handler]);
+///     // Keep the listeners attached for the lifetime of the page
+///     kh.forget();
 ///
 ///     // Manufacture the element we're gonna append
 ///     let val = document.create_element("p")?;
@@ -249,6 +641,8 @@ pub fn start_keywise_keyboard_handler(input: TokenStream) -> TokenStream {
 ///
 #[doc = arguments_doc!()]
 ///
+#[doc = key_identifiers_doc!()]
+///
 /// [`keydown`]: https://developer.mozilla.org/en-US/docs/Web/API/Element/keydown_event
 /// [key \[event\] handler]: https://en.wikipedia.org/wiki/Event_(computing)#Event_handler
 /// [`wasm_keyboard_example`]: https://github.com/JohnScience/wasm_keyboard_example
@@ -257,6 +651,17 @@ pub fn new_primitive_key_handler(input: TokenStream) -> TokenStream {
     use key_handlers::Args;
 
     let args = parse_macro_input!(input as Args);
+    if let Some(name) = args.simplified_only_argument() {
+        return syn::Error::new(
+            name.span(),
+            format!(
+                "`{}` argument is supported only by `new_simplified_key_handler!`",
+                name
+            ),
+        )
+        .to_compile_error()
+        .into();
+    }
 
     let mut ts = proc_macro2::TokenStream::new();
     args.extend_with_primitive_key_handler_expr(&mut ts);
@@ -269,13 +674,67 @@ pub fn new_primitive_key_handler(input: TokenStream) -> TokenStream {
 /// does not trigger the handler consequent times when the key is long-pressed
 /// and the handler closures accept the undecorated `state`. Internally, the
 /// simplified key handler uses the `state` augmented with `is_pressed: Cell<bool>`
-/// to account for the consequent triggers of `keydown` event when a key is long-pressed.
+/// to account for the consequent triggers of `keydown` event when a key is long-pressed,
+/// unless another [strategy](#strategies) is chosen.
 ///
 /// For finer control, consider using [`new_primitive_key_handler!`].
 ///
 /// One notable use of key handlers is creating and starting a keywise keyboard handler, e.g.
 /// using [`start_keywise_keyboard_handler!`].
 ///
+/// # Strategies
+///
+/// The way the initial `keydown` event of a press is told apart from the repeated ones can be
+/// chosen with `strategy = ` argument:
+///
+/// * `strategy = cell` (default) relies on `is_pressed: Cell<bool>`, which is set on `keydown`
+///   and reset on [`keyup`]. It works regardless of how the key handler is driven, e.g. in tests,
+///   but if `keyup` is lost (for example, when the focus changes mid-press), the key handler
+///   ignores the following presses until the key is released once again.
+/// * `strategy = repeat` relies on [`KeyboardEvent.repeat`] of the event that is being
+///   dispatched, which [`start_keywise_keyboard_handler!`] passes to the key handler. It can't
+///   get stuck after a lost `keyup`, but it requires the key handler to be driven by it and
+///   treats every `keydown` as a new press otherwise.
+///   It also trusts the browser, which on some platforms doesn't flag the repeated events.
+/// * `strategy = both` relies on `KeyboardEvent.repeat` when the event is available and falls
+///   back on `is_pressed` otherwise, so it recovers from a lost `keyup` in the browser and still
+///   suppresses the repeats when the key handler is driven directly.
+///
+/// `repeat` and `both` strategies require `KeyboardEvent` feature of `web_sys`.
+///
+/// ```rust,ignore
+/// let w_handler = new_simplified_key_handler!(
+///     KEY_W,
+///     state = (),
+///     keydown = { move |_state| start_walking() },
+///     keyup = { move |_state| stop_walking() },
+///     strategy = both
+/// );
+/// ```
+///
+/// # Auto-repeat
+///
+/// With `repeat = { delay = <expr>, interval = <expr> }` argument, the `keydown` event handler
+/// is called once when the key is pressed, then after `delay` milliseconds, and then every
+/// `interval` milliseconds until the key is released. The repetitions come from the timer
+/// of the key handler (i.e. [`setTimeout()`] of the global `window`) rather than from
+/// the OS repeat rate, which differs between users and platforms.
+///
+/// The repetitions stop on [`keyup`] or once the document loses focus, in which case
+/// `keyup` never arrives and the `keyup` event handler is called instead. The `keyup` event
+/// handler is called only once per press. The `Window` and `Document` features of `web_sys`
+/// are required.
+///
+/// ```rust,ignore
+/// let arrow_down_handler = new_simplified_key_handler!(
+///     ARROW_DOWN,
+///     state = (),
+///     keydown = { move |_state| select_next_item() },
+///     keyup = { move |_state| {} },
+///     repeat = { delay = 400, interval = 50 }
+/// );
+/// ```
+///
 /// # Example
 ///
 #[doc = simplified_key_handling_example_doc!()]
@@ -290,7 +749,12 @@ pub fn new_primitive_key_handler(input: TokenStream) -> TokenStream {
 ///
 #[doc = arguments_doc!()]
 ///
+#[doc = key_identifiers_doc!()]
+///
 /// [`keydown`]: https://developer.mozilla.org/en-US/docs/Web/API/Element/keydown_event
+/// [`keyup`]: https://developer.mozilla.org/en-US/docs/Web/API/Element/keyup_event
+/// [`setTimeout()`]: https://developer.mozilla.org/en-US/docs/Web/API/setTimeout
+/// [`KeyboardEvent.repeat`]: https://developer.mozilla.org/en-US/docs/Web/API/KeyboardEvent/repeat
 /// [key \[event\] handler]: https://en.wikipedia.org/wiki/Event_(computing)#Event_handler
 /// [`wasm_keyboard_example`]: https://github.com/JohnScience/wasm_keyboard_example
 #[proc_macro]
@@ -303,3 +767,258 @@ pub fn new_simplified_key_handler(input: TokenStream) -> TokenStream {
     args.extend_with_simplified_key_handler_expr(&mut ts);
     ts.into()
 }
+
+/// This macro creates a new "sequence" handler, which calls the `on_match` closure when
+/// the given ordered sequence of keys is pressed.
+///
+/// The sequence has to be started over when a wrong key is pressed or when more than `timeout`
+/// milliseconds pass between two consecutive keys of the sequence. Repeated [`keydown`] events
+/// of a long-pressed key are ignored, and so are modifier keys (e.g. `ShiftLeft` or `ControlRight`)
+/// that are not in the sequence.
+///
+/// Unlike key handlers, the sequence handler is a closure that accepts every
+/// [`web_sys::KeyboardEvent`] by reference. It can be used either as a standalone listener or as
+/// an observer (i.e. `.. => handler`) binding of [`start_keywise_keyboard_handler!`].
+///
+/// # Example
+///
+/// Code snippet below creates a sequence handler for the [Konami code] and starts
+/// a keywise keyboard handler that observes all keyboard events with it.
+///
+/// ```rust,ignore
+/// let konami_handler = new_sequence_handler!(
+///     [
+///         ArrowUp, ArrowUp, ArrowDown, ArrowDown,
+///         ArrowLeft, ArrowRight, ArrowLeft, ArrowRight,
+///         KeyB, KeyA,
+///     ],
+///     state = (),
+///     timeout = 1000,
+///     on_match = {
+///         let body = body.clone();
+///         let document = document.clone();
+///         move |_state| {
+///             let val = document.create_element("p").unwrap();
+///             val.set_inner_html("Cheat code activated!");
+///             body.append_child(&val).unwrap();
+///         }
+///     }
+/// );
+///
+/// start_keywise_keyboard_handler!(kh: Kh, document, [.. => konami_handler]);
+/// kh.forget();
+/// ```
+///
+/// In order to use the sequence handler as a standalone listener, it has to be wrapped into
+/// a [`Closure`]:
+///
+/// ```rust,ignore
+/// let listener = Closure::<dyn FnMut(KeyboardEvent)>::new(move |event: KeyboardEvent| {
+///     konami_handler(&event)
+/// });
+/// document.add_event_listener_with_callback("keydown", listener.as_ref().unchecked_ref())?;
+/// ```
+///
+/// # Macro input
+///
+/// The macro expects a comma-separated list of arguments adhering to the rules below.
+///
+/// The first argument must be a bracketed non-empty list of [key identifiers](#key-identifiers).
+///
+/// The second argument must be a `state = `-prefixed [expression](https://docs.rs/syn/latest/syn/enum.Expr.html)
+/// that should evaluate to the state of the sequence handler, e.g. `state = ()`.
+///
+/// The third argument must be a `timeout = `-prefixed expression that should evaluate
+/// to the maximum number of milliseconds between two consecutive keys of the sequence.
+///
+/// The fourth argument must be an `on_match = `-prefixed [block](https://docs.rs/syn/latest/syn/struct.Block.html)
+/// where the last statement must be a callable expression accepting the state by reference,
+/// just like the event handlers of [`new_primitive_key_handler!`].
+///
+#[doc = key_identifiers_doc!()]
+///
+/// [`keydown`]: https://developer.mozilla.org/en-US/docs/Web/API/Element/keydown_event
+/// [`web_sys::KeyboardEvent`]: https://docs.rs/web-sys/latest/web_sys/struct.KeyboardEvent.html
+/// [`Closure`]: https://docs.rs/wasm-bindgen/latest/wasm_bindgen/closure/struct.Closure.html
+/// [Konami code]: https://en.wikipedia.org/wiki/Konami_Code
+#[proc_macro]
+pub fn new_sequence_handler(input: TokenStream) -> TokenStream {
+    use sequence_handler::Args;
+
+    let args = parse_macro_input!(input as Args);
+
+    let mut ts = proc_macro2::TokenStream::new();
+    args.extend_with_sequence_handler_expr(&mut ts);
+    ts.into()
+}
+
+/// This macro creates a new "long-press" [key \[event\] handler], which distinguishes between
+/// long and short presses of the key.
+///
+/// The `on_long_press` closure is called once the key has been held for `threshold`
+/// milliseconds, and the `on_short_press` closure is called if the key is released earlier.
+/// Each press of the key calls exactly one of them.
+///
+/// The initial [`keydown`] event of the key starts a timer for `threshold` milliseconds, which
+/// calls `on_long_press` if the key is still held, while [`keyup`] cancels it. The timer
+/// requires `Window` feature of `web_sys`. Additionally, the duration of the press is checked
+/// with the `clock` on each repeated `keydown` event of the held key and on `keyup`, so
+/// `on_long_press` is called even without a timer, e.g. outside of a browser. The `clock` can
+/// be replaced, e.g. with a fake one in tests.
+///
+/// Like other key handlers, the long-press handler can be used in [`start_keywise_keyboard_handler!`].
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let space_handler = new_long_press_key_handler!(
+///     SPACE,
+///     state = (),
+///     threshold = 500,
+///     on_long_press = { move |_state| charge_jump() },
+///     on_short_press = { move |_state| jump() }
+/// );
+///
+/// start_keywise_keyboard_handler!(kh: Kh, document, [SPACE => space_handler]);
+/// kh.forget();
+/// ```
+///
+/// With a fake clock, the handler can be driven without a browser:
+///
+/// ```rust,ignore
+/// let now = Rc::new(Cell::new(0.0));
+/// let handler = new_long_press_key_handler!(
+///     SPACE,
+///     state = Cell::new(0),
+///     threshold = 500,
+///     clock = { let now = now.clone(); move || now.get() },
+///     on_long_press = { move |count| count.set(count.get() + 10) },
+///     on_short_press = { move |count| count.set(count.get() + 1) }
+/// );
+///
+/// handler.handle_keydown();
+/// now.set(600.0);
+/// handler.handle_keydown();
+/// handler.handle_keyup();
+/// ```
+///
+/// # Macro input
+///
+/// The macro expects a comma-separated list of arguments adhering to the rules below.
+///
+/// The first argument must be a [key identifier](#key-identifiers), e.g. `SPACE`.
+///
+/// The second argument must be a `state = `-prefixed [expression](https://docs.rs/syn/latest/syn/enum.Expr.html)
+/// that should evaluate to the state of the key handler, e.g. `state = ()`.
+///
+/// The third argument must be a `threshold = `-prefixed expression that should evaluate
+/// to the number of milliseconds the key has to be held for the press to be long.
+///
+/// The optional fourth argument must be a `clock = `-prefixed expression that should evaluate
+/// to a closure returning the current time in milliseconds as `f64`. By default,
+/// [`Performance.now()`] of the global `window` is used, which requires `Window` and `Performance`
+/// features of `web_sys`.
+///
+/// The last two arguments must be `on_long_press = ` and `on_short_press = `-prefixed
+/// [blocks](https://docs.rs/syn/latest/syn/struct.Block.html) where the last statement must be
+/// a callable expression accepting the state by reference, just like the event handlers of
+/// [`new_primitive_key_handler!`].
+///
+#[doc = key_identifiers_doc!()]
+///
+/// [`keydown`]: https://developer.mozilla.org/en-US/docs/Web/API/Element/keydown_event
+/// [`keyup`]: https://developer.mozilla.org/en-US/docs/Web/API/Element/keyup_event
+/// [key \[event\] handler]: https://en.wikipedia.org/wiki/Event_(computing)#Event_handler
+/// [`Performance.now()`]: https://developer.mozilla.org/en-US/docs/Web/API/Performance/now
+#[proc_macro]
+pub fn new_long_press_key_handler(input: TokenStream) -> TokenStream {
+    use long_press_handler::Args;
+
+    let args = parse_macro_input!(input as Args);
+
+    let mut ts = proc_macro2::TokenStream::new();
+    args.extend_with_long_press_key_handler_expr(&mut ts);
+    ts.into()
+}
+
+/// This macro creates a new "multi-tap" [key \[event\] handler], which counts consecutive
+/// taps of the key, e.g. for double-tap dashes or "double-Shift to search".
+///
+/// A tap is consecutive if the key is pressed again within `interval` milliseconds after
+/// it was released. Repeated [`keydown`] events of a long-pressed key are not taps.
+///
+/// The count can be handled in either or both of the following ways:
+///
+/// * `on_tap` closure is called right away on each tap with the number of consecutive taps
+///   so far (1, 2, 3…), so that the handler can react to the specific count without delay;
+/// * `on_settled` closure is called with the final number of consecutive taps once `interval`
+///   milliseconds pass after the last release without another tap.
+///
+/// The interval is timed with [`setTimeout()`] of the global `window`, which requires `Window`
+/// feature of `web_sys`. If the timer cannot be set, the taps are settled right away.
+///
+/// Like other key handlers, the multi-tap handler can be used in [`start_keywise_keyboard_handler!`].
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let shift_handler = new_multi_tap_key_handler!(
+///     SHIFT_LEFT,
+///     state = (),
+///     interval = 300,
+///     on_tap = {
+///         move |_state, count| {
+///             if count == 2 {
+///                 open_search();
+///             }
+///         }
+///     }
+/// );
+///
+/// let d_handler = new_multi_tap_key_handler!(
+///     KEY_D,
+///     state = (),
+///     interval = 250,
+///     on_settled = {
+///         move |_state, count| match count {
+///             1 => step_right(),
+///             _ => dash_right(),
+///         }
+///     }
+/// );
+///
+/// start_keywise_keyboard_handler!(kh: Kh, document, [SHIFT_LEFT => shift_handler, KEY_D => d_handler]);
+/// kh.forget();
+/// ```
+///
+/// # Macro input
+///
+/// The macro expects a comma-separated list of arguments adhering to the rules below.
+///
+/// The first argument must be a [key identifier](#key-identifiers), e.g. `SHIFT_LEFT`.
+///
+/// The second argument must be a `state = `-prefixed [expression](https://docs.rs/syn/latest/syn/enum.Expr.html)
+/// that should evaluate to the state of the key handler, e.g. `state = ()`.
+///
+/// The third argument must be an `interval = `-prefixed expression that should evaluate
+/// to the maximum number of milliseconds between the release of the key and the next tap.
+///
+/// The rest of the arguments, at least one, must be `on_tap = ` or `on_settled = `-prefixed
+/// [blocks](https://docs.rs/syn/latest/syn/struct.Block.html) where the last statement must be
+/// a callable expression accepting the state by reference and the number of taps as `u32`.
+///
+#[doc = key_identifiers_doc!()]
+///
+/// [`keydown`]: https://developer.mozilla.org/en-US/docs/Web/API/Element/keydown_event
+/// [key \[event\] handler]: https://en.wikipedia.org/wiki/Event_(computing)#Event_handler
+/// [`setTimeout()`]: https://developer.mozilla.org/en-US/docs/Web/API/setTimeout
+#[proc_macro]
+pub fn new_multi_tap_key_handler(input: TokenStream) -> TokenStream {
+    use multi_tap_handler::Args;
+
+    let args = parse_macro_input!(input as Args);
+
+    let mut ts = proc_macro2::TokenStream::new();
+    args.extend_with_multi_tap_key_handler_expr(&mut ts);
+    ts.into()
+}
@@ -0,0 +1,333 @@
+use quote::quote;
+use syn::{parse::Parse, Token};
+
+use crate::{
+    key_code::Key,
+    key_handlers::{parse_event_handler, parse_name, KeyEventHandler},
+    EVENTS,
+};
+
+runtime_items! { long_press_items =>
+    /// State of the presses of the key of a long-press handler.
+    struct __LongPress {
+        /// The time of the initial `keydown` of the key that is currently held
+        pressed_at: ::core::cell::Cell<::core::option::Option<f64>>,
+        /// Whether `on_long_press` was already called for the current press
+        is_long_press_handled: ::core::cell::Cell<bool>,
+        /// Incremented on each press and release, so that the timers of the previous presses
+        /// don't call `on_long_press`
+        generation: ::core::cell::Cell<u32>,
+        threshold: f64,
+    }
+
+    impl __LongPress {
+        fn new(threshold: f64) -> Self {
+            __LongPress {
+                pressed_at: ::core::cell::Cell::new(::core::option::Option::None),
+                is_long_press_handled: ::core::cell::Cell::new(false),
+                generation: ::core::cell::Cell::new(0),
+                threshold,
+            }
+        }
+
+        /// Handles `keydown` at the given time.
+        ///
+        /// Returns the generation of the press for the timer to start if the press is new,
+        /// and whether `on_long_press` has to be called.
+        fn press(&self, now: f64) -> (::core::option::Option<u32>, bool) {
+            // Repeated `keydown` events of the held key keep the initial time
+            match self.pressed_at.get() {
+                ::core::option::Option::Some(pressed_at) => (
+                    ::core::option::Option::None,
+                    self.is_long(now - pressed_at),
+                ),
+                ::core::option::Option::None => {
+                    self.pressed_at.set(::core::option::Option::Some(now));
+                    self.is_long_press_handled.set(false);
+                    self.generation.set(self.generation.get().wrapping_add(1));
+                    (::core::option::Option::Some(self.generation.get()), false)
+                }
+            }
+        }
+
+        /// Handles the expiry of the timer started for the press of the given generation.
+        ///
+        /// Returns whether `on_long_press` has to be called.
+        fn expire(&self, generation: u32) -> bool {
+            self.generation.get() == generation
+                && self.pressed_at.get().is_some()
+                && self.is_long(self.threshold)
+        }
+
+        /// Handles `keyup` at the given time.
+        ///
+        /// Returns `Some(true)` if `on_long_press` has to be called, `Some(false)`
+        /// if `on_short_press` has to be called and `None` otherwise.
+        fn release(&self, now: f64) -> ::core::option::Option<bool> {
+            self.generation.set(self.generation.get().wrapping_add(1));
+            let pressed_at = self.pressed_at.take()?;
+            if self.is_long_press_handled.replace(false) {
+                return ::core::option::Option::None;
+            }
+            ::core::option::Option::Some(now - pressed_at >= self.threshold)
+        }
+
+        /// Marks the current press as long if it has been held for `duration` milliseconds
+        /// and `on_long_press` wasn't called for it yet.
+        fn is_long(&self, duration: f64) -> bool {
+            if self.is_long_press_handled.get() || duration < self.threshold {
+                return false;
+            }
+            self.is_long_press_handled.set(true);
+            true
+        }
+    }
+}
+
+pub(super) struct Args {
+    /// Key identifier that corresponds to some [`KeyboardEvent.code`].
+    ///
+    /// [`KeyboardEvent.code`]: https://developer.mozilla.org/en-US/docs/Web/API/KeyboardEvent/code
+    pub(super) key: Key,
+    /// The state of the long-press handler, which is passed by reference to `on_long_press`
+    /// and `on_short_press` event handlers.
+    pub(super) state: syn::Expr,
+    /// The number of milliseconds the key has to be held for the press to be long.
+    pub(super) threshold: syn::Expr,
+    /// Closure that returns the current time in milliseconds, if given.
+    ///
+    /// Otherwise, [`Performance.now()`] of the global `window` is used.
+    ///
+    /// [`Performance.now()`]: https://developer.mozilla.org/en-US/docs/Web/API/Performance/now
+    pub(super) clock: Option<syn::Expr>,
+    /// Event handler that is called once the key has been held for `threshold` milliseconds.
+    pub(super) on_long_press: KeyEventHandler,
+    /// Event handler that is called when the key is released before `threshold` milliseconds.
+    pub(super) on_short_press: KeyEventHandler,
+}
+
+impl Parse for Args {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let key = input.parse()?;
+        parse_name(input, "state")?;
+        let state = input.parse()?;
+        parse_name(input, "threshold")?;
+        let threshold = input.parse()?;
+        let clock = {
+            let fork = input.fork();
+            let is_clock = fork.parse::<Token![,]>().is_ok()
+                && fork
+                    .parse::<syn::Ident>()
+                    .map_or(false, |ident| ident == "clock");
+            if is_clock {
+                parse_name(input, "clock")?;
+                Some(input.parse()?)
+            } else {
+                None
+            }
+        };
+        let on_long_press = parse_event_handler(input, "on_long_press")?;
+        let on_short_press = parse_event_handler(input, "on_short_press")?;
+        input.parse::<Option<Token![,]>>()?;
+        Ok(Args {
+            key,
+            state,
+            threshold,
+            clock,
+            on_long_press,
+            on_short_press,
+        })
+    }
+}
+
+impl Args {
+    /// Responsible for implementation of [crate::new_long_press_key_handler] macro.
+    pub(super) fn extend_with_long_press_key_handler_expr(
+        &self,
+        ts: &mut proc_macro2::TokenStream,
+    ) {
+        let Self {
+            key,
+            state,
+            threshold,
+            clock,
+            on_long_press,
+            on_short_press,
+        } = self;
+
+        let variant_path = key.to_variant_path();
+        let clock = match clock {
+            Some(clock) => quote!(#clock),
+            None => quote!(|| {
+                ::web_sys::window()
+                    .and_then(|window| window.performance())
+                    .map_or(0.0, |performance| performance.now())
+            }),
+        };
+        // The number of event handlers depends on `keypress` feature
+        let inferred_tys = EVENTS.map(|_| quote!(_));
+        #[cfg(feature = "keypress")]
+        let keypress_handler = quote!(, |_: &_| ());
+        #[cfg(not(feature = "keypress"))]
+        let keypress_handler = quote!();
+
+        let long_press_items = long_press_items();
+
+        ts.extend(quote!(
+            {
+                #long_press_items
+                let __state = #state;
+                // Helps the type inference of the closure parameters
+                fn constrain<S, F: Fn(&S)>(_state: &S, f: F) -> F {
+                    f
+                }
+                fn constrain_clock<F: Fn() -> f64>(f: F) -> F {
+                    f
+                }
+                let __on_long_press = constrain(&__state, #on_long_press);
+                let __on_short_press = constrain(&__state, #on_short_press);
+                ::wasm_keyboard::implementors::KeyHandler::<{ #variant_path as u8 }, _, #(#inferred_tys),*>::new(
+                    // The timer that detects the long press outlives the borrow of the state
+                    ::std::rc::Rc::new((
+                        __LongPress::new((#threshold) as f64),
+                        constrain_clock(#clock),
+                        __on_long_press,
+                        __on_short_press,
+                        __state,
+                    )),
+                    |inner: &::std::rc::Rc<_>| {
+                        let (press, clock, on_long_press, _, state) = &**inner;
+                        let (__generation, is_long_press) = press.press(clock());
+                        if is_long_press {
+                            on_long_press(state);
+                        }
+                        let __generation = match __generation {
+                            ::core::option::Option::Some(__generation) => __generation,
+                            ::core::option::Option::None => return,
+                        };
+                        let __inner = ::std::rc::Rc::downgrade(inner);
+                        let __callback = ::wasm_bindgen::closure::Closure::once_into_js(move || {
+                            let __inner = match __inner.upgrade() {
+                                ::core::option::Option::Some(__inner) => __inner,
+                                // The key handler is already dropped
+                                ::core::option::Option::None => return,
+                            };
+                            let (press, _, on_long_press, _, state) = &*__inner;
+                            if press.expire(__generation) {
+                                on_long_press(state);
+                            }
+                        });
+                        // Without the timer, the duration of the press is still checked
+                        // on repeated `keydown` events and on `keyup`
+                        if let ::core::option::Option::Some(__window) = ::web_sys::window() {
+                            let _ = __window.set_timeout_with_callback_and_timeout_and_arguments_0(
+                                ::wasm_bindgen::JsCast::unchecked_ref(&__callback),
+                                press.threshold.ceil() as i32,
+                            );
+                        }
+                    },
+                    |inner: &::std::rc::Rc<_>| {
+                        let (press, clock, on_long_press, on_short_press, state) = &**inner;
+                        match press.release(clock()) {
+                            ::core::option::Option::Some(true) => on_long_press(state),
+                            ::core::option::Option::Some(false) => on_short_press(state),
+                            ::core::option::Option::None => (),
+                        }
+                    }
+                    #keypress_handler
+                )
+            }
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Event handler called by the long-press handler.
+    #[derive(Debug, PartialEq)]
+    enum Call {
+        LongPress,
+        ShortPress,
+    }
+
+    fn press(press: &__LongPress, now: f64) -> (Option<u32>, Vec<Call>) {
+        let (generation, is_long_press) = press.press(now);
+        let calls = if is_long_press {
+            vec![Call::LongPress]
+        } else {
+            vec![]
+        };
+        (generation, calls)
+    }
+
+    fn expire(press: &__LongPress, generation: u32) -> Vec<Call> {
+        if press.expire(generation) {
+            vec![Call::LongPress]
+        } else {
+            vec![]
+        }
+    }
+
+    fn release(press: &__LongPress, now: f64) -> Vec<Call> {
+        match press.release(now) {
+            Some(true) => vec![Call::LongPress],
+            Some(false) => vec![Call::ShortPress],
+            None => vec![],
+        }
+    }
+
+    #[test]
+    fn timer_calls_on_long_press_while_the_key_is_held() {
+        let long_press = __LongPress::new(500.0);
+        let (generation, calls) = press(&long_press, 0.0);
+        assert!(calls.is_empty());
+        assert_eq!(expire(&long_press, generation.unwrap()), [Call::LongPress]);
+        // Neither repeated `keydown` events nor `keyup` call it again
+        assert_eq!(press(&long_press, 600.0), (None, vec![]));
+        assert!(release(&long_press, 700.0).is_empty());
+    }
+
+    #[test]
+    fn release_before_threshold_is_a_short_press() {
+        let long_press = __LongPress::new(500.0);
+        let (generation, _) = press(&long_press, 0.0);
+        assert_eq!(release(&long_press, 200.0), [Call::ShortPress]);
+        // The timer of the released press is cancelled
+        assert!(expire(&long_press, generation.unwrap()).is_empty());
+    }
+
+    #[test]
+    fn timer_of_a_previous_press_is_ignored() {
+        let long_press = __LongPress::new(500.0);
+        let (first, _) = press(&long_press, 0.0);
+        assert_eq!(release(&long_press, 100.0), [Call::ShortPress]);
+        let (second, _) = press(&long_press, 300.0);
+        assert_ne!(first, second);
+        assert!(expire(&long_press, first.unwrap()).is_empty());
+        assert_eq!(expire(&long_press, second.unwrap()), [Call::LongPress]);
+    }
+
+    #[test]
+    fn repeated_keydown_detects_long_press_without_timer() {
+        let long_press = __LongPress::new(500.0);
+        press(&long_press, 0.0);
+        assert_eq!(press(&long_press, 300.0), (None, vec![]));
+        assert_eq!(press(&long_press, 500.0), (None, vec![Call::LongPress]));
+        assert!(release(&long_press, 800.0).is_empty());
+    }
+
+    #[test]
+    fn release_detects_long_press_without_timer() {
+        let long_press = __LongPress::new(500.0);
+        press(&long_press, 0.0);
+        assert_eq!(release(&long_press, 500.0), [Call::LongPress]);
+    }
+
+    #[test]
+    fn release_without_press_calls_nothing() {
+        let long_press = __LongPress::new(500.0);
+        assert!(release(&long_press, 0.0).is_empty());
+    }
+}
@@ -0,0 +1,378 @@
+use quote::quote;
+use syn::{parse::Parse, Token};
+
+runtime_items! { is_apple_platform_fn =>
+    /// Returns whether the value of `Navigator.platform` is one of Apple platforms,
+    /// i.e. "MacIntel", "iPhone", "iPad", etc., where `Mod` resolves to `Meta`.
+    fn __is_apple_platform(platform: &str) -> bool {
+        platform.starts_with("Mac") || platform.starts_with("iP")
+    }
+}
+
+/// Modifier key that can be a part of a key binding.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Modifier {
+    Ctrl,
+    Shift,
+    Alt,
+    Meta,
+    /// Platform-neutral modifier, which is resolved at runtime to [`Modifier::Meta`]
+    /// on Apple platforms and to [`Modifier::Ctrl`] elsewhere.
+    Mod,
+}
+
+impl Modifier {
+    const PHYSICAL: [Modifier; 4] = [
+        Modifier::Ctrl,
+        Modifier::Shift,
+        Modifier::Alt,
+        Modifier::Meta,
+    ];
+
+    /// Returns the index of the modifier in the array of modifier states built by
+    /// [`Modifiers::extend_with_modifier_states`].
+    ///
+    /// [`Modifier::Mod`] comes last because it has no index of its own and is
+    /// resolved at runtime instead.
+    fn index(self) -> usize {
+        match self {
+            Modifier::Ctrl => 0,
+            Modifier::Shift => 1,
+            Modifier::Alt => 2,
+            Modifier::Meta => 3,
+            Modifier::Mod => 4,
+        }
+    }
+}
+
+/// Set of modifiers of a key binding, e.g. `Ctrl+Shift+` in `Ctrl+Shift+KEY_Z`.
+///
+/// A binding with modifiers matches only when exactly these modifiers are held, unless
+/// `Any` is listed among them, in which case the other modifiers are ignored. A binding
+/// without modifiers matches regardless of the modifiers that are held.
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct Modifiers {
+    /// Modifiers that must be held for the binding to match.
+    pub(crate) required: Vec<Modifier>,
+    /// Whether the modifiers that are not required are ignored.
+    pub(crate) any: bool,
+}
+
+impl Parse for Modifiers {
+    /// Parses the `+`-terminated modifiers preceding the key identifier, if any.
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let mut required = Vec::new();
+        let mut any = false;
+        while input.peek(syn::Ident) && input.peek2(Token![+]) {
+            let ident = input.parse::<syn::Ident>()?;
+            input.parse::<Token![+]>()?;
+            let modifier = match ident.to_string().as_str() {
+                "Ctrl" => Modifier::Ctrl,
+                "Shift" => Modifier::Shift,
+                "Alt" => Modifier::Alt,
+                "Meta" => Modifier::Meta,
+                "Mod" => Modifier::Mod,
+                "Any" => {
+                    any = true;
+                    continue;
+                }
+                _ => {
+                    return Err(syn::Error::new(
+                        ident.span(),
+                        format!(
+                            "unknown modifier `{}`, expected one of `Ctrl`, `Shift`, `Alt`, `Meta`, `Mod` or `Any`",
+                            ident
+                        ),
+                    ))
+                }
+            };
+            if required.contains(&modifier) {
+                return Err(syn::Error::new(
+                    ident.span(),
+                    format!("modifier `{}` is listed more than once", ident),
+                ));
+            }
+            // `Mod+Ctrl+` would mean `Ctrl+` on non-Apple platforms but `Ctrl+Meta+` on Apple ones
+            let overlaps_mod = match modifier {
+                Modifier::Mod => {
+                    required.contains(&Modifier::Ctrl) || required.contains(&Modifier::Meta)
+                }
+                Modifier::Ctrl | Modifier::Meta => required.contains(&Modifier::Mod),
+                Modifier::Shift | Modifier::Alt => false,
+            };
+            if overlaps_mod {
+                return Err(syn::Error::new(
+                    ident.span(),
+                    "modifier `Mod` stands for `Ctrl` on non-Apple platforms and for `Meta` \
+                    on Apple ones, so it cannot be combined with either",
+                ));
+            }
+            required.push(modifier);
+        }
+        // A binding without modifiers matches regardless of the modifiers that are held
+        if required.is_empty() {
+            any = true;
+        }
+        required.sort_by_key(|modifier| modifier.index());
+        Ok(Modifiers { required, any })
+    }
+}
+
+impl std::fmt::Display for Modifiers {
+    /// Writes the modifiers as they are listed in the binding, e.g. `Ctrl+Shift+`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for modifier in &self.required {
+            let name = match modifier {
+                Modifier::Ctrl => "Ctrl",
+                Modifier::Shift => "Shift",
+                Modifier::Alt => "Alt",
+                Modifier::Meta => "Meta",
+                Modifier::Mod => "Mod",
+            };
+            write!(f, "{}+", name)?;
+        }
+        if self.any && !self.required.is_empty() {
+            f.write_str("Any+")?;
+        }
+        Ok(())
+    }
+}
+
+impl Modifiers {
+    /// Returns whether the platform-neutral `Mod` modifier is listed.
+    pub(crate) fn has_mod(&self) -> bool {
+        self.required.contains(&Modifier::Mod)
+    }
+
+    /// Returns the modifier that `Mod` among these modifiers has to resolve to for them to
+    /// coincide with the other modifiers, which don't list `Mod`, e.g. [`Modifier::Ctrl`]
+    /// for `Mod+Shift+` and `Ctrl+Shift+`.
+    pub(crate) fn coincidence_with(&self, other: &Modifiers) -> Option<Modifier> {
+        if !self.has_mod() || other.has_mod() || self.any != other.any {
+            return None;
+        }
+        [Modifier::Ctrl, Modifier::Meta]
+            .into_iter()
+            .find(|&resolved| {
+                let mut required = self
+                    .required
+                    .iter()
+                    .copied()
+                    .filter(|&modifier| modifier != Modifier::Mod)
+                    .collect::<Vec<_>>();
+                if !required.contains(&resolved) {
+                    required.push(resolved);
+                }
+                required.sort_by_key(|modifier| modifier.index());
+                required == other.required
+            })
+    }
+
+    /// Extends the token stream with the expression that evaluates to the index of the modifier
+    /// in the array of modifier states that `Mod` resolves to on the given platform.
+    ///
+    /// The platform is expected to be a value implementing [`AsRef<str>`] with the value of
+    /// [`Navigator.platform`], e.g. `"MacIntel"`. If it is not given, it's derived from
+    /// `Navigator.platform` of the global `window`.
+    ///
+    /// [`Navigator.platform`]: https://developer.mozilla.org/en-US/docs/Web/API/Navigator/platform
+    pub(crate) fn extend_with_mod_index(
+        ts: &mut proc_macro2::TokenStream,
+        platform: Option<&syn::Expr>,
+    ) {
+        let platform = match platform {
+            Some(platform) => quote!(#platform),
+            None => quote!(::web_sys::window()
+                .and_then(|window| window.navigator().platform().ok())
+                .unwrap_or_default()),
+        };
+        let [ctrl_index, meta_index] = [Modifier::Ctrl.index(), Modifier::Meta.index()];
+        let is_apple_platform_fn = is_apple_platform_fn();
+        ts.extend(quote!({
+            #is_apple_platform_fn
+            let __platform = #platform;
+            let __platform: &str = ::core::convert::AsRef::<str>::as_ref(&__platform);
+            if __is_apple_platform(__platform) {
+                #meta_index
+            } else {
+                #ctrl_index
+            }
+        }));
+    }
+
+    /// Key for sorting the bindings of the same key from the most specific to the least specific.
+    ///
+    /// Bindings with more modifiers take precedence, and bindings that match exactly
+    /// take precedence over the ones that ignore the other modifiers.
+    pub(crate) fn specificity(&self) -> impl Ord {
+        std::cmp::Reverse((self.required.len(), !self.any))
+    }
+
+    /// Extends the token stream with the expression that evaluates to the array of states of
+    /// the modifiers (`[ctrl, shift, alt, meta]`) for the given [`KeyboardEvent`].
+    ///
+    /// [`KeyboardEvent`]: https://docs.rs/web-sys/latest/web_sys/struct.KeyboardEvent.html
+    pub(crate) fn extend_with_modifier_states(
+        ts: &mut proc_macro2::TokenStream,
+        event: &syn::Ident,
+    ) {
+        ts.extend(quote!([
+            #event.ctrl_key(),
+            #event.shift_key(),
+            #event.alt_key(),
+            #event.meta_key(),
+        ]));
+    }
+
+    /// Extends the token stream with the boolean expression that checks whether the binding
+    /// matches the array of states of the modifiers.
+    ///
+    /// `mod_index` is the expression that evaluates to the index that `Mod` resolves to,
+    /// as produced by [`Modifiers::extend_with_mod_index`].
+    pub(crate) fn extend_with_condition(
+        &self,
+        ts: &mut proc_macro2::TokenStream,
+        modifier_states: &syn::Ident,
+        mod_index: &proc_macro2::TokenStream,
+    ) {
+        let Self { required, any } = self;
+        let index = |modifier: &Modifier| match modifier {
+            Modifier::Mod => mod_index.clone(),
+            modifier => {
+                let index = modifier.index();
+                quote!(#index)
+            }
+        };
+        if *any && required.is_empty() {
+            ts.extend(quote!(true));
+        } else if *any {
+            let indices = required.iter().map(index);
+            ts.extend(quote!(#(#modifier_states[#indices])&&*));
+        } else if self.has_mod() {
+            let expected_states = Modifier::PHYSICAL.map(|modifier| required.contains(&modifier));
+            let mod_index = index(&Modifier::Mod);
+            ts.extend(quote!(#modifier_states == {
+                let mut __expected_states = [#(#expected_states),*];
+                __expected_states[#mod_index] = true;
+                __expected_states
+            }));
+        } else {
+            let expected_states = Modifier::PHYSICAL.map(|modifier| required.contains(&modifier));
+            ts.extend(quote!(#modifier_states == [#(#expected_states),*]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn modifiers(s: &str) -> Modifiers {
+        // The key identifier only terminates the modifiers
+        syn::parse::Parser::parse_str(
+            |input: syn::parse::ParseStream| {
+                let modifiers = input.parse::<Modifiers>()?;
+                input.parse::<syn::Ident>()?;
+                Ok(modifiers)
+            },
+            s,
+        )
+        .unwrap()
+    }
+
+    fn modifiers_error(s: &str) -> String {
+        syn::parse::Parser::parse_str(
+            |input: syn::parse::ParseStream| input.parse::<Modifiers>(),
+            s,
+        )
+        .err()
+        .unwrap()
+        .to_string()
+    }
+
+    #[test]
+    fn modifiers_are_sorted() {
+        let shift_ctrl = modifiers("Shift+Ctrl+KEY_K");
+        assert_eq!(shift_ctrl.to_string(), "Ctrl+Shift+");
+        assert!(shift_ctrl == modifiers("Ctrl+Shift+KEY_K"));
+        assert!(!shift_ctrl.any);
+    }
+
+    #[test]
+    fn any_ignores_other_modifiers() {
+        let ctrl_any = modifiers("Any+Ctrl+KEY_K");
+        assert!(ctrl_any.any && ctrl_any.required == [Modifier::Ctrl]);
+        assert_eq!(ctrl_any.to_string(), "Ctrl+Any+");
+        // A binding without modifiers matches regardless of the modifiers that are held
+        let none = modifiers("KEY_K");
+        assert!(none.any && none.required.is_empty());
+        assert_eq!(none.to_string(), "");
+    }
+
+    #[test]
+    fn mod_cannot_be_combined_with_ctrl_or_meta() {
+        let error = "modifier `Mod` stands for `Ctrl` on non-Apple platforms and for `Meta` \
+            on Apple ones, so it cannot be combined with either";
+        assert_eq!(modifiers_error("Mod+Ctrl+KEY_K"), error);
+        assert_eq!(modifiers_error("Meta+Shift+Mod+KEY_K"), error);
+        assert!(modifiers("Mod+Shift+Alt+KEY_K").has_mod());
+    }
+
+    #[test]
+    fn unknown_and_repeated_modifiers_are_rejected() {
+        assert_eq!(
+            modifiers_error("Super+KEY_K"),
+            "unknown modifier `Super`, expected one of `Ctrl`, `Shift`, `Alt`, `Meta`, `Mod` or `Any`"
+        );
+        assert_eq!(
+            modifiers_error("Ctrl+Ctrl+KEY_K"),
+            "modifier `Ctrl` is listed more than once"
+        );
+    }
+
+    #[test]
+    fn apple_platforms() {
+        for platform in ["MacIntel", "MacPPC", "iPhone", "iPad", "iPod"] {
+            assert!(__is_apple_platform(platform), "{}", platform);
+        }
+    }
+
+    #[test]
+    fn non_apple_platforms() {
+        for platform in ["Win32", "Linux x86_64", "Linux armv8l", "X11", ""] {
+            assert!(!__is_apple_platform(platform), "{}", platform);
+        }
+    }
+
+    #[test]
+    fn mod_index_calls_the_platform_check() {
+        let mut ts = proc_macro2::TokenStream::new();
+        Modifiers::extend_with_mod_index(&mut ts, None);
+        let ts = ts.to_string();
+        assert!(ts.contains("fn __is_apple_platform"));
+        assert!(ts.contains("__is_apple_platform (__platform)"));
+    }
+
+    #[test]
+    fn mod_coincides_with_ctrl_and_meta() {
+        let resolved =
+            modifiers("Mod+Shift+KEY_K").coincidence_with(&modifiers("Ctrl+Shift+KEY_K"));
+        assert!(resolved == Some(Modifier::Ctrl));
+        let resolved = modifiers("Mod+KEY_K").coincidence_with(&modifiers("Meta+KEY_K"));
+        assert!(resolved == Some(Modifier::Meta));
+    }
+
+    #[test]
+    fn mod_does_not_coincide_with_other_modifiers() {
+        let mod_k = modifiers("Mod+KEY_K");
+        assert!(mod_k.coincidence_with(&modifiers("Alt+KEY_K")).is_none());
+        assert!(mod_k
+            .coincidence_with(&modifiers("Ctrl+Shift+KEY_K"))
+            .is_none());
+        assert!(mod_k
+            .coincidence_with(&modifiers("Ctrl+Any+KEY_K"))
+            .is_none());
+        assert!(mod_k.coincidence_with(&modifiers("Mod+KEY_K")).is_none());
+        assert!(modifiers("Ctrl+KEY_K").coincidence_with(&mod_k).is_none());
+    }
+}
@@ -0,0 +1,337 @@
+use quote::quote;
+use syn::{parse::Parse, Token};
+
+use crate::{
+    key_code::Key,
+    key_handlers::{parse_name, KeyEventHandler},
+    EVENTS,
+};
+
+runtime_items! { multi_tap_items =>
+    /// State of the taps of the key of a multi-tap handler.
+    struct __MultiTap {
+        /// Whether the key is currently held
+        is_pressed: ::core::cell::Cell<bool>,
+        /// The number of consecutive taps so far
+        count: ::core::cell::Cell<u32>,
+        /// Incremented on each tap, so that the timers of the previous taps
+        /// don't settle the taps
+        generation: ::core::cell::Cell<u32>,
+    }
+
+    impl __MultiTap {
+        fn new() -> Self {
+            __MultiTap {
+                is_pressed: ::core::cell::Cell::new(false),
+                count: ::core::cell::Cell::new(0),
+                generation: ::core::cell::Cell::new(0),
+            }
+        }
+
+        /// Handles `keydown`.
+        ///
+        /// Returns the number of consecutive taps so far if the press is a new tap.
+        fn tap(&self) -> ::core::option::Option<u32> {
+            // Repeated `keydown` events of the held key are not taps
+            if self.is_pressed.replace(true) {
+                return ::core::option::Option::None;
+            }
+            self.generation.set(self.generation.get().wrapping_add(1));
+            self.count.set(self.count.get() + 1);
+            ::core::option::Option::Some(self.count.get())
+        }
+
+        /// Handles `keyup`.
+        ///
+        /// Returns the generation of the last tap for the timer to start if there are taps
+        /// to settle.
+        fn release(&self) -> ::core::option::Option<u32> {
+            self.is_pressed.set(false);
+            if self.count.get() == 0 {
+                return ::core::option::Option::None;
+            }
+            ::core::option::Option::Some(self.generation.get())
+        }
+
+        /// Handles the expiry of the timer started for the tap of the given generation.
+        ///
+        /// Returns the number of the settled taps if `on_settled` has to be called.
+        fn settle(&self, generation: u32) -> ::core::option::Option<u32> {
+            if self.generation.get() != generation {
+                return ::core::option::Option::None;
+            }
+            match self.count.replace(0) {
+                0 => ::core::option::Option::None,
+                count => ::core::option::Option::Some(count),
+            }
+        }
+    }
+}
+
+pub(super) struct Args {
+    /// Key identifier that corresponds to some [`KeyboardEvent.code`].
+    ///
+    /// [`KeyboardEvent.code`]: https://developer.mozilla.org/en-US/docs/Web/API/KeyboardEvent/code
+    pub(super) key: Key,
+    /// The state of the multi-tap handler, which is passed by reference to `on_tap`
+    /// and `on_settled` event handlers.
+    pub(super) state: syn::Expr,
+    /// The maximum number of milliseconds between the release of the key and the next tap
+    /// for the taps to be consecutive.
+    pub(super) interval: syn::Expr,
+    /// Event handler that is called on each tap with the number of consecutive taps so far.
+    pub(super) on_tap: Option<KeyEventHandler>,
+    /// Event handler that is called with the number of consecutive taps once `interval`
+    /// milliseconds pass without another tap.
+    pub(super) on_settled: Option<KeyEventHandler>,
+}
+
+impl Parse for Args {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let key = input.parse()?;
+        parse_name(input, "state")?;
+        let state = input.parse()?;
+        parse_name(input, "interval")?;
+        let interval = input.parse()?;
+        let mut on_tap = None;
+        let mut on_settled = None;
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            let name = input.parse::<syn::Ident>()?;
+            input.parse::<Token![=]>()?;
+            let slot = match name.to_string().as_str() {
+                "on_tap" => &mut on_tap,
+                "on_settled" => &mut on_settled,
+                _ => {
+                    return Err(syn::Error::new(
+                        name.span(),
+                        format!(
+                            "unknown event handler `{}`, expected `on_tap` or `on_settled`",
+                            name
+                        ),
+                    ))
+                }
+            };
+            if slot.is_some() {
+                return Err(syn::Error::new(
+                    name.span(),
+                    format!("event handler `{}` is specified more than once", name),
+                ));
+            }
+            *slot = Some(KeyEventHandler::from_stmts(
+                input.parse::<syn::Block>()?.stmts,
+                &name.to_string(),
+                input.span(),
+            )?);
+        }
+        if on_tap.is_none() && on_settled.is_none() {
+            return Err(syn::Error::new(
+                input.span(),
+                "expected `on_tap` or `on_settled` event handler",
+            ));
+        }
+        Ok(Args {
+            key,
+            state,
+            interval,
+            on_tap,
+            on_settled,
+        })
+    }
+}
+
+impl Args {
+    /// Responsible for implementation of [crate::new_multi_tap_key_handler] macro.
+    pub(super) fn extend_with_multi_tap_key_handler_expr(&self, ts: &mut proc_macro2::TokenStream) {
+        let Self {
+            key,
+            state,
+            interval,
+            on_tap,
+            on_settled,
+        } = self;
+
+        let variant_path = key.to_variant_path();
+        let [on_tap, on_settled] = [on_tap, on_settled].map(|handler| match handler {
+            Some(handler) => quote!(#handler),
+            None => quote!(|_, _| ()),
+        });
+        // The number of event handlers depends on `keypress` feature
+        let inferred_tys = EVENTS.map(|_| quote!(_));
+        #[cfg(feature = "keypress")]
+        let keypress_handler = quote!(, |_: &_| ());
+        #[cfg(not(feature = "keypress"))]
+        let keypress_handler = quote!();
+
+        let multi_tap_items = multi_tap_items();
+
+        ts.extend(quote!(
+            {
+                #multi_tap_items
+                let __state = #state;
+                // Helps the type inference of the closure parameters
+                fn constrain<S, F: Fn(&S, u32)>(_state: &S, f: F) -> F {
+                    f
+                }
+                let __on_tap = constrain(&__state, #on_tap);
+                let __on_settled = constrain(&__state, #on_settled);
+                ::wasm_keyboard::implementors::KeyHandler::<{ #variant_path as u8 }, _, #(#inferred_tys),*>::new(
+                    // The timer that settles the taps outlives the borrow of the state
+                    ::std::rc::Rc::new((
+                        __MultiTap::new(),
+                        (#interval) as i32,
+                        __on_tap,
+                        __on_settled,
+                        __state,
+                    )),
+                    |inner: &::std::rc::Rc<_>| {
+                        let (taps, _, on_tap, _, state) = &**inner;
+                        if let ::core::option::Option::Some(count) = taps.tap() {
+                            on_tap(state, count);
+                        }
+                    },
+                    |inner: &::std::rc::Rc<_>| {
+                        let (taps, interval, _, on_settled, state) = &**inner;
+                        let __generation = match taps.release() {
+                            ::core::option::Option::Some(__generation) => __generation,
+                            ::core::option::Option::None => return,
+                        };
+                        let __inner = ::std::rc::Rc::downgrade(inner);
+                        let __callback = ::wasm_bindgen::closure::Closure::once_into_js(move || {
+                            let __inner = match __inner.upgrade() {
+                                ::core::option::Option::Some(__inner) => __inner,
+                                // The key handler is already dropped
+                                ::core::option::Option::None => return,
+                            };
+                            let (taps, _, _, on_settled, state) = &*__inner;
+                            if let ::core::option::Option::Some(count) = taps.settle(__generation) {
+                                on_settled(state, count);
+                            }
+                        });
+                        let __is_scheduled = match ::web_sys::window() {
+                            ::core::option::Option::Some(__window) => __window
+                                .set_timeout_with_callback_and_timeout_and_arguments_0(
+                                    ::wasm_bindgen::JsCast::unchecked_ref(&__callback),
+                                    *interval,
+                                )
+                                .is_ok(),
+                            ::core::option::Option::None => false,
+                        };
+                        // Without the timer, the taps are settled right away
+                        if !__is_scheduled {
+                            if let ::core::option::Option::Some(count) = taps.settle(__generation) {
+                                on_settled(state, count);
+                            }
+                        }
+                    }
+                    #keypress_handler
+                )
+            }
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(args: &str) -> String {
+        match syn::parse_str::<Args>(args) {
+            Ok(_) => panic!("`{}` is expected to be rejected", args),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn expansion(args: &str) -> String {
+        let mut ts = proc_macro2::TokenStream::new();
+        syn::parse_str::<Args>(args)
+            .unwrap()
+            .extend_with_multi_tap_key_handler_expr(&mut ts);
+        ts.to_string()
+    }
+
+    #[test]
+    fn either_or_both_event_handlers_can_be_given() {
+        let args = syn::parse_str::<Args>(
+            "KEY_D, state = (), interval = 250, on_settled = { |_, _| () }, on_tap = { f },",
+        )
+        .unwrap();
+        let interval = &args.interval;
+        assert_eq!(quote!(#interval).to_string(), "250");
+        assert!(args.on_tap.is_some() && args.on_settled.is_some());
+        let args =
+            syn::parse_str::<Args>("KEY_D, state = (), interval = 250, on_tap = { f }").unwrap();
+        assert!(args.on_tap.is_some() && args.on_settled.is_none());
+    }
+
+    #[test]
+    fn missing_duplicate_and_unknown_event_handlers_are_rejected() {
+        assert_eq!(
+            parse_error("KEY_D, state = (), interval = 250"),
+            "expected `on_tap` or `on_settled` event handler"
+        );
+        assert_eq!(
+            parse_error("KEY_D, state = (), interval = 250, on_tap = { f }, on_tap = { g }"),
+            "event handler `on_tap` is specified more than once"
+        );
+        assert_eq!(
+            parse_error("KEY_D, state = (), interval = 250, on_press = { f }"),
+            "unknown event handler `on_press`, expected `on_tap` or `on_settled`"
+        );
+        assert_eq!(
+            parse_error("KEY_D, interval = 250, state = (), on_tap = { f }"),
+            "expected `state`"
+        );
+    }
+
+    #[test]
+    fn multi_tap_handlers_keep_the_key_code_in_their_type() {
+        let expansion = expansion("KEY_D, state = (), interval = 250, on_tap = { f }");
+        assert!(expansion.contains(
+            ":: wasm_keyboard :: implementors :: KeyHandler :: < { :: wasm_keyboard :: \
+            uievents_code :: KeyboardEventCode :: KeyD as u8 }"
+        ));
+        assert!(expansion.contains("struct __MultiTap"));
+        // The omitted event handler does nothing
+        assert!(expansion.contains("let __on_settled = constrain (& __state , | _ , _ | ())"));
+    }
+
+    #[test]
+    fn consecutive_taps_are_counted_until_settled() {
+        let taps = __MultiTap::new();
+        assert_eq!(taps.tap(), Some(1));
+        let first = taps.release().unwrap();
+        assert_eq!(taps.tap(), Some(2));
+        let second = taps.release().unwrap();
+        // The timer of the previous tap doesn't settle the taps
+        assert_eq!(taps.settle(first), None);
+        assert_eq!(taps.settle(second), Some(2));
+        // Nothing is left to settle afterwards
+        assert_eq!(taps.settle(second), None);
+        assert_eq!(taps.release(), None);
+        assert_eq!(taps.tap(), Some(1));
+    }
+
+    #[test]
+    fn repeated_keydown_is_not_a_tap() {
+        let taps = __MultiTap::new();
+        assert_eq!(taps.tap(), Some(1));
+        assert_eq!(taps.tap(), None);
+        let generation = taps.release().unwrap();
+        assert_eq!(taps.settle(generation), Some(1));
+    }
+
+    #[test]
+    fn held_key_is_not_settled() {
+        let taps = __MultiTap::new();
+        taps.tap();
+        let first = taps.release().unwrap();
+        taps.tap();
+        assert_eq!(taps.settle(first), None);
+        let second = taps.release().unwrap();
+        assert_eq!(taps.settle(second), Some(2));
+    }
+}
@@ -0,0 +1,295 @@
+use quote::quote;
+use syn::{bracketed, parse::Parse, punctuated::Punctuated, Token};
+
+use crate::{
+    key_code::Key,
+    key_handlers::{parse_name, KeyEventHandler},
+};
+
+runtime_items! { advance_sequence_fn =>
+    /// Returns the number of keys of the sequence that are pressed so far after the key with
+    /// the given code is pressed `elapsed` milliseconds after the previous key, and whether
+    /// the whole sequence is pressed.
+    ///
+    /// Modifier keys that are not in the sequence are ignored, since they are usually pressed
+    /// on their way to the next key, e.g. `Shift` for a capital letter.
+    fn __advance_sequence(
+        sequence: &[&str],
+        fallbacks: &[usize],
+        mut progress: usize,
+        code: &str,
+        elapsed: f64,
+        timeout: f64,
+    ) -> (usize, bool) {
+        if elapsed > timeout {
+            progress = 0;
+        }
+        let is_modifier = ["Shift", "Control", "Alt", "Meta"]
+            .iter()
+            .any(|prefix| code.starts_with(prefix));
+        if is_modifier && !sequence.contains(&code) {
+            return (progress, false);
+        }
+        while progress > 0 && sequence[progress] != code {
+            progress = fallbacks[progress - 1];
+        }
+        if sequence[progress] == code {
+            progress += 1;
+        }
+        if progress == sequence.len() {
+            (0, true)
+        } else {
+            (progress, false)
+        }
+    }
+}
+
+pub(super) struct Args {
+    /// Ordered sequence of keys that triggers the handler, e.g. the [Konami code].
+    ///
+    /// [Konami code]: https://en.wikipedia.org/wiki/Konami_Code
+    pub(super) keys: Vec<Key>,
+    /// The state of the sequence handler, which is passed by reference to `on_match`
+    /// event handler.
+    pub(super) state: syn::Expr,
+    /// The maximum number of milliseconds between two consecutive keys of the sequence.
+    ///
+    /// Once it is exceeded, the sequence has to be started over.
+    pub(super) timeout: syn::Expr,
+    /// Event handler that is called when the whole sequence is pressed.
+    pub(super) on_match: KeyEventHandler,
+}
+
+impl Parse for Args {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let keys = {
+            let content;
+            let brackets = bracketed!(content in input);
+            let keys = Punctuated::<Key, Token![,]>::parse_terminated(&content)?
+                .into_iter()
+                .collect::<Vec<_>>();
+            if keys.is_empty() {
+                return Err(syn::Error::new(
+                    brackets.span,
+                    "expected a non-empty sequence of keys",
+                ));
+            }
+            keys
+        };
+        parse_name(input, "state")?;
+        let state = input.parse()?;
+        parse_name(input, "timeout")?;
+        let timeout = input.parse()?;
+        parse_name(input, "on_match")?;
+        let on_match = KeyEventHandler::from_stmts(
+            input.parse::<syn::Block>()?.stmts,
+            "on_match",
+            input.span(),
+        )?;
+        input.parse::<Option<Token![,]>>()?;
+        Ok(Args {
+            keys,
+            state,
+            timeout,
+            on_match,
+        })
+    }
+}
+
+impl Args {
+    /// Computes the [failure function] of the Knuth–Morris–Pratt algorithm for the sequence.
+    ///
+    /// For each `i`, it is the length of the longest proper prefix of `keys[..=i]`
+    /// that is also its suffix. This way, pressing `ArrowUp` thrice still makes progress
+    /// on a sequence that starts with `ArrowUp, ArrowUp, ArrowDown`.
+    ///
+    /// [failure function]: https://en.wikipedia.org/wiki/Knuth%E2%80%93Morris%E2%80%93Pratt_algorithm
+    fn fallbacks(&self) -> Vec<usize> {
+        let keys = &self.keys;
+        let mut fallbacks = vec![0; keys.len()];
+        let mut len = 0;
+        for i in 1..keys.len() {
+            while len > 0 && keys[i].code != keys[len].code {
+                len = fallbacks[len - 1];
+            }
+            if keys[i].code == keys[len].code {
+                len += 1;
+            }
+            fallbacks[i] = len;
+        }
+        fallbacks
+    }
+
+    /// Responsible for implementation of [crate::new_sequence_handler] macro.
+    pub(super) fn extend_with_sequence_handler_expr(&self, ts: &mut proc_macro2::TokenStream) {
+        let Self {
+            keys,
+            state,
+            timeout,
+            on_match,
+        } = self;
+
+        let len = keys.len();
+        let code_lits = keys.iter().map(Key::to_code_lit);
+        let fallbacks = self.fallbacks();
+        let advance_sequence_fn = advance_sequence_fn();
+
+        ts.extend(quote!(
+            {
+                #advance_sequence_fn
+                const SEQUENCE: [&str; #len] = [#(#code_lits),*];
+                const FALLBACKS: [usize; #len] = [#(#fallbacks),*];
+                let __state = #state;
+                let __timeout: f64 = (#timeout) as f64;
+                // Helps the type inference of the closure parameter
+                fn constrain<S, F: Fn(&S)>(_state: &S, f: F) -> F {
+                    f
+                }
+                let __on_match = constrain(&__state, #on_match);
+                // The number of keys of the sequence that were pressed so far
+                let __progress = ::core::cell::Cell::new(0usize);
+                let __last_time_stamp = ::core::cell::Cell::new(f64::NEG_INFINITY);
+                move |event: &::web_sys::KeyboardEvent| {
+                    if event.type_() != "keydown" || event.repeat() {
+                        return;
+                    }
+                    let time_stamp = event.time_stamp();
+                    let (progress, is_matched) = __advance_sequence(
+                        &SEQUENCE,
+                        &FALLBACKS,
+                        __progress.get(),
+                        &event.code(),
+                        time_stamp - __last_time_stamp.replace(time_stamp),
+                        __timeout,
+                    );
+                    __progress.set(progress);
+                    if is_matched {
+                        __on_match(&__state);
+                    }
+                }
+            }
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fallbacks(keys: &str) -> Vec<usize> {
+        let input = format!(
+            "[{}], state = (), timeout = 500, on_match = {{ |_state| {{}} }}",
+            keys
+        );
+        syn::parse_str::<Args>(&input).unwrap().fallbacks()
+    }
+
+    /// Presses the keys with the given codes and elapsed times, and returns the indices of
+    /// the presses that completed the sequence.
+    fn matches(sequence: &[&str], presses: &[(&str, f64)]) -> Vec<usize> {
+        let keys = sequence.join(", ");
+        let fallbacks = fallbacks(&keys);
+        let mut progress = 0;
+        let mut matches = Vec::new();
+        for (i, &(code, elapsed)) in presses.iter().enumerate() {
+            let (new_progress, is_matched) =
+                __advance_sequence(sequence, &fallbacks, progress, code, elapsed, 500.0);
+            progress = new_progress;
+            if is_matched {
+                matches.push(i);
+            }
+        }
+        matches
+    }
+
+    #[test]
+    fn failure_table_of_overlapping_prefixes() {
+        assert_eq!(fallbacks("KeyA, KeyA, KeyB"), [0, 1, 0]);
+        assert_eq!(
+            fallbacks("KeyA, KeyB, KeyA, KeyB, KeyA, KeyC"),
+            [0, 0, 1, 2, 3, 0]
+        );
+        assert_eq!(fallbacks("KeyA, KeyA, KeyA"), [0, 1, 2]);
+        assert_eq!(fallbacks("KeyA, KeyB, KeyC"), [0, 0, 0]);
+    }
+
+    #[test]
+    fn failure_table_compares_key_codes() {
+        assert_eq!(
+            fallbacks(r#"KEY_A, "KeyA", KeyboardEventCode::KeyB"#),
+            [0, 1, 0]
+        );
+    }
+
+    #[test]
+    fn repeated_prefix_keeps_progress() {
+        let sequence = ["KeyA", "KeyA", "KeyB"];
+        let presses = [("KeyA", 0.0), ("KeyA", 0.0), ("KeyA", 0.0), ("KeyB", 0.0)];
+        assert_eq!(matches(&sequence, &presses), [3]);
+    }
+
+    #[test]
+    fn partial_match_falls_back() {
+        let sequence = ["KeyA", "KeyB", "KeyA", "KeyC"];
+        let presses = [
+            ("KeyA", 0.0),
+            ("KeyB", 0.0),
+            ("KeyA", 0.0),
+            ("KeyB", 0.0),
+            ("KeyA", 0.0),
+            ("KeyC", 0.0),
+        ];
+        assert_eq!(matches(&sequence, &presses), [5]);
+    }
+
+    #[test]
+    fn sequence_starts_over_after_a_match() {
+        let sequence = ["KeyA", "KeyA"];
+        let presses = [("KeyA", 0.0), ("KeyA", 0.0), ("KeyA", 0.0), ("KeyA", 0.0)];
+        assert_eq!(matches(&sequence, &presses), [1, 3]);
+    }
+
+    #[test]
+    fn timeout_resets_progress() {
+        let sequence = ["KeyA", "KeyB"];
+        let presses = [("KeyA", 0.0), ("KeyB", 501.0)];
+        assert!(matches(&sequence, &presses).is_empty());
+    }
+
+    #[test]
+    fn timed_out_key_can_start_the_sequence() {
+        let sequence = ["KeyA", "KeyB"];
+        let presses = [("KeyA", 0.0), ("KeyA", 501.0), ("KeyB", 500.0)];
+        assert_eq!(matches(&sequence, &presses), [2]);
+    }
+
+    #[test]
+    fn modifiers_outside_of_the_sequence_keep_progress() {
+        let sequence = ["KeyA", "KeyB"];
+        let presses = [
+            ("KeyA", 0.0),
+            ("ShiftLeft", 0.0),
+            ("ControlRight", 0.0),
+            ("KeyB", 0.0),
+        ];
+        assert_eq!(matches(&sequence, &presses), [3]);
+        // A modifier of the sequence is a key like any other
+        let sequence = ["KeyA", "AltLeft", "KeyB"];
+        let presses = [("KeyA", 0.0), ("MetaLeft", 0.0), ("KeyB", 0.0)];
+        assert!(matches(&sequence, &presses).is_empty());
+        let presses = [
+            ("KeyA", 0.0),
+            ("AltLeft", 0.0),
+            ("AltRight", 0.0),
+            ("KeyB", 0.0),
+        ];
+        assert_eq!(matches(&sequence, &presses), [3]);
+        let presses = [
+            ("KeyA", 0.0),
+            ("AltLeft", 0.0),
+            ("KeyA", 0.0),
+            ("KeyB", 0.0),
+        ];
+        assert!(matches(&sequence, &presses).is_empty());
+    }
+}
@@ -1,47 +1,384 @@
 use std::marker::PhantomData;
 
-use crate::EVENTS;
+use crate::{
+    key_code::{Key, KeyPattern},
+    key_handlers::{extend_with_dispatch_to_key_handler_fn, extend_with_outcome_trait},
+    modifiers::{Modifier, Modifiers},
+    EVENTS,
+};
 use quote::quote;
-use strum::EnumCount;
 use syn::{bracketed, parse::Parse, punctuated::Punctuated, token::FatArrow, Expr, Token};
-use uievents_code::KeyboardEventCode;
 
 use super::EVENT_COUNT;
 
+/// Values of [`KeyboardEvent.code`] of the modifier keys, which are pressed before the key
+/// of the chord that follows a prefix and hence don't complete the prefix.
+///
+/// [`KeyboardEvent.code`]: https://developer.mozilla.org/en-US/docs/Web/API/KeyboardEvent/code
+const MODIFIER_KEY_CODES: [&str; 8] = [
+    "ControlLeft",
+    "ControlRight",
+    "ShiftLeft",
+    "ShiftRight",
+    "AltLeft",
+    "AltRight",
+    "MetaLeft",
+    "MetaRight",
+];
+
 pub(super) struct Args {
+    /// Whether the macro was invoked with the `try` keyword, in which case the instance
+    /// is bound to a `Result` instead of panicking when an event listener cannot be added.
+    pub(super) fallible: bool,
     pub(super) instance_name: syn::Ident,
     pub(super) ty_name: syn::Ident,
-    pub(super) target: syn::Ident,
-    pub(super) key_paths: Vec<syn::Path>,
-    pub(super) key_handlers_exprs: Vec<Expr>,
+    /// Expression that evaluates to the target of the event listeners or to anything that
+    /// dereferences to [`web_sys::EventTarget`], e.g. `web_sys::window().unwrap()`.
+    ///
+    /// [`web_sys::EventTarget`]: https://docs.rs/web-sys/latest/web_sys/struct.EventTarget.html
+    pub(super) target: Expr,
+    /// Key handlers of both the top-level bindings and the bindings that follow prefixes.
+    pub(super) key_handlers: Vec<KeyHandler>,
+    /// Prefixes of `Ctrl+KEY_X => [..]` bindings, in the order of their occurrence.
+    pub(super) prefixes: Vec<Prefix>,
+    /// Handlers of `.. => handler` bindings, which observe every keyboard event.
+    pub(super) observers: Vec<Expr>,
+    /// Handler of `_ => handler` binding, which is called with the code of the keys
+    /// that match no binding.
+    pub(super) fallback: Option<Expr>,
+    pub(super) options: Options,
+}
+
+/// Optional `name = value` arguments that follow the list of bindings.
+#[derive(Default)]
+pub(super) struct Options {
+    /// Platform that the `Mod` modifier is resolved for, i.e. a value implementing
+    /// [`AsRef<str>`] with the value of [`Navigator.platform`].
+    ///
+    /// [`Navigator.platform`]: https://developer.mozilla.org/en-US/docs/Web/API/Navigator/platform
+    platform: Option<Expr>,
+    /// Target whose `blur` and `visibilitychange` events release the keys that are currently
+    /// held, if not the global `window`.
+    blur_target: Option<Expr>,
+    /// The number of milliseconds after which a pending prefix is abandoned.
+    prefix_timeout: Option<Expr>,
+    /// Callback that is called with the description of the prefix when it becomes pending
+    /// and with `None` when it stops being pending.
+    on_pending: Option<Expr>,
+    /// Callback that is called with the description of the pending prefix and the code
+    /// of the key that doesn't continue it, or `None` if the prefix was abandoned.
+    on_invalid: Option<Expr>,
+    /// Whether `preventDefault()` is called for the events of the keys that match a binding
+    /// without `#[prevent_default]` attribute.
+    prevent_default: Option<syn::LitBool>,
+    /// Whether `stopPropagation()` is called for the events of the keys that match a binding
+    /// without `#[stop_propagation]` attribute.
+    stop_propagation: Option<syn::LitBool>,
+    /// Whether the keyboard event listeners are called in the capture phase.
+    capture: Option<Expr>,
+    /// Whether the keyboard event listeners never call `preventDefault()`.
+    passive: Option<Expr>,
+    /// Whether the keyboard event listeners are removed after their first call.
+    once: Option<Expr>,
+}
+
+impl Options {
+    /// Returns the options of the keyboard event listeners, i.e. the names of the fields of
+    /// [`AddEventListenerOptions`] and their values, if any is given.
+    ///
+    /// [`AddEventListenerOptions`]: https://docs.rs/web-sys/latest/web_sys/struct.AddEventListenerOptions.html
+    fn listener_options(&self) -> Vec<(syn::Ident, &Expr)> {
+        [
+            ("capture", &self.capture),
+            ("passive", &self.passive),
+            ("once", &self.once),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((quote::format_ident!("{}", name), value.as_ref()?)))
+        .collect()
+    }
+}
+
+/// Sets the value of the option unless it is already set.
+fn set_option<T>(slot: &mut Option<T>, name: &syn::Ident, value: T) -> syn::Result<()> {
+    if slot.is_some() {
+        return Err(syn::Error::new(
+            name.span(),
+            format!("option `{}` is specified more than once", name),
+        ));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+impl Parse for Options {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let mut options = Options::default();
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            let name = input.parse::<syn::Ident>()?;
+            input.parse::<Token![=]>()?;
+            match name.to_string().as_str() {
+                "platform" => set_option(&mut options.platform, &name, input.parse()?)?,
+                "blur_target" => set_option(&mut options.blur_target, &name, input.parse()?)?,
+                "prefix_timeout" => set_option(&mut options.prefix_timeout, &name, input.parse()?)?,
+                "on_pending" => set_option(&mut options.on_pending, &name, input.parse()?)?,
+                "on_invalid" => set_option(&mut options.on_invalid, &name, input.parse()?)?,
+                "prevent_default" => set_option(&mut options.prevent_default, &name, input.parse()?)?,
+                "stop_propagation" => set_option(&mut options.stop_propagation, &name, input.parse()?)?,
+                "capture" => set_option(&mut options.capture, &name, input.parse()?)?,
+                "passive" => set_option(&mut options.passive, &name, input.parse()?)?,
+                "once" => set_option(&mut options.once, &name, input.parse()?)?,
+                _ => {
+                    return Err(syn::Error::new(
+                        name.span(),
+                        format!(
+                            "unknown option `{}`, expected one of `platform`, `blur_target`, `prefix_timeout`, `on_pending`, `on_invalid`, `prevent_default`, `stop_propagation`, `capture`, `passive` or `once`",
+                            name
+                        ),
+                    ))
+                }
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Whether the events of the keys that match a binding are consumed, as specified with
+/// `#[prevent_default]` and `#[stop_propagation]` attributes of the binding, e.g.
+/// `#[prevent_default] SPACE => jump_handler` or `#[stop_propagation = false] KEY_W => w_handler`.
+///
+/// Unspecified values fall back to the options of the keywise keyboard handler.
+#[derive(Default)]
+struct Consumption {
+    prevent_default: Option<bool>,
+    stop_propagation: Option<bool>,
 }
 
-struct KeyHandler {
-    key_path: syn::Path,
+impl Parse for Consumption {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let mut consumption = Consumption::default();
+        for attr in input.call(syn::Attribute::parse_outer)? {
+            let meta = attr.parse_meta()?;
+            let slot = match meta.path().get_ident() {
+                Some(ident) if ident == "prevent_default" => &mut consumption.prevent_default,
+                Some(ident) if ident == "stop_propagation" => &mut consumption.stop_propagation,
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        meta.path(),
+                        "unknown attribute, expected `prevent_default` or `stop_propagation`",
+                    ))
+                }
+            };
+            let value = match &meta {
+                syn::Meta::Path(_) => true,
+                syn::Meta::NameValue(syn::MetaNameValue {
+                    lit: syn::Lit::Bool(lit),
+                    ..
+                }) => lit.value,
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        &meta,
+                        "expected either no value or a `bool` literal, e.g. `#[prevent_default = false]`",
+                    ))
+                }
+            };
+            if slot.replace(value).is_some() {
+                return Err(syn::Error::new_spanned(
+                    meta.path(),
+                    "attribute is specified more than once",
+                ));
+            }
+        }
+        Ok(consumption)
+    }
+}
+
+pub(super) struct KeyHandler {
+    consumption: Consumption,
+    /// Keys with sets of modifiers that are bound to the same key handler, e.g.
+    /// `KEY_W | ARROW_UP`.
+    alternates: Vec<(Modifiers, Key)>,
+    /// Whether some of the alternates is a key range or a key group, e.g. `Digit0..=Digit9`,
+    /// and the key handler expression is a closure, which then receives the
+    /// [`KeyboardEventCode`] of the pressed key instead of being a [`wasm_keyboard::KeyHandler`].
+    ///
+    /// [`KeyboardEventCode`]: https://docs.rs/uievents-code/latest/uievents_code/enum.KeyboardEventCode.html
+    /// [`wasm_keyboard::KeyHandler`]: https://docs.rs/wasm_keyboard/latest/wasm_keyboard/trait.KeyHandler.html
+    receives_code: bool,
     // Fat arrow is not used in the struct but it is used in the parse function.
     fat_arrow: PhantomData<FatArrow>,
     key_handler_expr: Expr,
+    /// Index of the prefix that has to be pending for the binding to match, if any.
+    prefix: Option<usize>,
+}
+
+/// Parses `|`-separated [key patterns](KeyPattern) with sets of modifiers, e.g.
+/// `KEY_W | Shift+ARROW_UP` or `Digit0..=Digit9 | Numpad*`, and expands them into keys.
+///
+/// Returns the keys with their sets of modifiers and whether some of the patterns can match
+/// more than one key.
+fn parse_alternates(input: syn::parse::ParseStream) -> syn::Result<(Vec<(Modifiers, Key)>, bool)> {
+    let mut alternates = Vec::new();
+    let mut has_multi_key_pattern = false;
+    loop {
+        let modifiers = input.parse::<Modifiers>()?;
+        let pattern = input.parse::<KeyPattern>()?;
+        has_multi_key_pattern |= pattern.is_multi_key();
+        alternates.extend(
+            pattern
+                .into_keys()?
+                .into_iter()
+                .map(|key| (modifiers.clone(), key)),
+        );
+        if !input.peek(Token![|]) {
+            break;
+        }
+        input.parse::<Token![|]>()?;
+    }
+    Ok((alternates, has_multi_key_pattern))
 }
 
 impl Parse for KeyHandler {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
-        let key_path = input.parse()?;
+        let consumption = input.parse()?;
+        let (alternates, has_multi_key_pattern) = parse_alternates(input)?;
         let fat_arrow = match input.parse::<Token![=>]>() {
             Ok(_fat_arrow) => PhantomData,
             Err(e) => return Err(e),
         };
+        if input.peek(syn::token::Bracket) {
+            return Err(syn::Error::new(
+                input.span(),
+                "prefixes cannot be nested, expected a key handler",
+            ));
+        }
         let expr = input.parse()?;
+        // Any other expression is a key handler shared by the keys, like with alternates
+        let receives_code = has_multi_key_pattern && matches!(expr, Expr::Closure(_));
+        // The closure receives the `KeyboardEventCode` of each of its keys
+        if receives_code {
+            for (_, key) in &alternates {
+                key.known_code("the closures of key ranges and groups")?;
+            }
+        }
 
         Ok(KeyHandler {
-            key_path,
+            consumption,
+            alternates,
+            receives_code,
             fat_arrow,
             key_handler_expr: expr,
+            prefix: None,
         })
     }
 }
 
+/// Key with a set of modifiers that has to be pressed before one of the bindings
+/// that follow it, e.g. `Ctrl+KEY_X` in `Ctrl+KEY_X => [Ctrl+KEY_S => save]`.
+pub(super) struct Prefix {
+    consumption: Consumption,
+    modifiers: Modifiers,
+    key: Key,
+}
+
+impl Prefix {
+    /// Returns the human-readable description of the prefix, e.g. `Ctrl+KeyX`.
+    fn description(&self) -> String {
+        format!("{}{}", self.modifiers, self.key.name())
+    }
+}
+
+/// Entry of the list of bindings.
+enum Binding {
+    Key(KeyHandler),
+    /// `prefix => [bindings]` binding.
+    Prefix(Prefix, Vec<KeyHandler>),
+    /// `.. => handler` binding.
+    Observer(Expr),
+    /// `_ => handler` binding.
+    Fallback(Token![_], Expr),
+}
+
+impl Parse for Binding {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        if input.peek(Token![#]) {
+            let fork = input.fork();
+            fork.call(syn::Attribute::parse_outer)?;
+            if fork.peek(Token![..]) || fork.peek(Token![_]) {
+                return Err(syn::Error::new(
+                    input.span(),
+                    "only key handlers and prefixes can have attributes",
+                ));
+            }
+        }
+        if input.peek(Token![..]) {
+            input.parse::<Token![..]>()?;
+            input.parse::<Token![=>]>()?;
+            return Ok(Binding::Observer(input.parse()?));
+        }
+        if input.peek(Token![_]) {
+            let underscore = input.parse()?;
+            input.parse::<Token![=>]>()?;
+            return Ok(Binding::Fallback(underscore, input.parse()?));
+        }
+        // Only the prefix can be followed by a bracketed list of bindings
+        let fork = input.fork();
+        fork.parse::<Consumption>()?;
+        parse_alternates(&fork)?;
+        fork.parse::<Token![=>]>()?;
+        if !fork.peek(syn::token::Bracket) {
+            return input.parse().map(Binding::Key);
+        }
+        let consumption = input.parse()?;
+        let modifiers = input.parse()?;
+        let key = match input.parse()? {
+            KeyPattern::Key(key) => key,
+            KeyPattern::Range(start, _) => {
+                return Err(syn::Error::new(start.span, "prefixes cannot be key ranges"))
+            }
+            KeyPattern::Group(ident) => {
+                return Err(syn::Error::new(
+                    ident.span(),
+                    "prefixes cannot be key groups",
+                ))
+            }
+        };
+        if input.peek(Token![|]) {
+            return Err(syn::Error::new(
+                input.span(),
+                "prefixes cannot have alternates",
+            ));
+        }
+        input.parse::<Token![=>]>()?;
+        let content;
+        let brackets = bracketed!(content in input);
+        let key_handlers = Punctuated::<KeyHandler, Token![,]>::parse_terminated(&content)?
+            .into_iter()
+            .collect::<Vec<_>>();
+        if key_handlers.is_empty() {
+            return Err(syn::Error::new(
+                brackets.span,
+                "expected at least one binding after the prefix",
+            ));
+        }
+        Ok(Binding::Prefix(
+            Prefix {
+                consumption,
+                modifiers,
+                key,
+            },
+            key_handlers,
+        ))
+    }
+}
+
 impl Parse for Args {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let fallible = input.parse::<Option<Token![try]>>()?.is_some();
         let instance_name = input.parse()?;
         input.parse::<Token![:]>()?;
         let ty_name = input.parse()?;
@@ -50,41 +387,655 @@ impl Parse for Args {
         input.parse::<Token![,]>()?;
         let content;
         bracketed!(content in input);
-        let mut key_paths = Vec::<syn::Path>::with_capacity(KeyboardEventCode::COUNT);
-        let mut key_handlers_exprs = Vec::<Expr>::with_capacity(KeyboardEventCode::COUNT);
-        for KeyHandler {
-            key_path,
-            fat_arrow: _,
-            key_handler_expr,
-        } in Punctuated::<KeyHandler, Token![,]>::parse_terminated(&content)?
-        {
-            key_paths.push(key_path);
-            key_handlers_exprs.push(key_handler_expr);
+        let mut key_handlers = Vec::new();
+        let mut prefixes = Vec::new();
+        let mut observers = Vec::new();
+        let mut fallback = None;
+        for binding in Punctuated::<Binding, Token![,]>::parse_terminated(&content)? {
+            match binding {
+                Binding::Key(key_handler) => key_handlers.push(key_handler),
+                Binding::Prefix(prefix, prefixed_key_handlers) => {
+                    let prefix_idx = prefixes.len();
+                    prefixes.push(prefix);
+                    key_handlers.extend(prefixed_key_handlers.into_iter().map(|key_handler| {
+                        KeyHandler {
+                            prefix: Some(prefix_idx),
+                            ..key_handler
+                        }
+                    }));
+                }
+                Binding::Observer(observer) => observers.push(observer),
+                Binding::Fallback(underscore, handler) => {
+                    if fallback.replace(handler).is_some() {
+                        return Err(syn::Error::new(
+                            underscore.span,
+                            "fallback handler is provided more than once",
+                        ));
+                    }
+                }
+            }
         }
-        match key_paths.len() {
+        match key_handlers.len() + observers.len() + usize::from(fallback.is_some()) {
             0 => Err(syn::Error::new(
                 proc_macro2::Span::call_site(),
                 "No key handlers were provided.",
             )),
             _ => Ok(()),
         }?;
-        Ok(Args {
+        let options: Options = input.parse()?;
+        if prefixes.is_empty() {
+            let prefix_options = [
+                &options.prefix_timeout,
+                &options.on_pending,
+                &options.on_invalid,
+            ];
+            if let Some(option) = prefix_options.into_iter().flatten().next() {
+                return Err(syn::Error::new_spanned(
+                    option,
+                    "the option is applicable only to handlers with prefix bindings",
+                ));
+            }
+        }
+        let args = Args {
+            fallible,
             instance_name,
             ty_name,
             target,
-            key_paths,
-            key_handlers_exprs,
-        })
+            key_handlers,
+            prefixes,
+            observers,
+            fallback,
+            options,
+        };
+        args.check_for_duplicate_bindings()?;
+        Ok(args)
     }
 }
 
+/// Binding that a key press can be dispatched to.
+#[derive(Clone, Copy)]
+enum Candidate {
+    /// Index of the key handler and index of the alternate among its keys.
+    KeyHandler(usize, usize),
+    /// Index of the prefix.
+    Prefix(usize),
+}
+
 impl Args {
+    /// Returns all candidates, first the key handlers and then the prefixes.
+    fn candidates(&self) -> impl Iterator<Item = Candidate> + '_ {
+        self.key_handlers
+            .iter()
+            .enumerate()
+            .flat_map(|(i, key_handler)| {
+                (0..key_handler.alternates.len()).map(move |j| Candidate::KeyHandler(i, j))
+            })
+            .chain((0..self.prefixes.len()).map(Candidate::Prefix))
+    }
+
+    /// Returns the modifiers and the key of the binding of the candidate.
+    fn chord(&self, candidate: Candidate) -> (&Modifiers, &Key) {
+        match candidate {
+            Candidate::KeyHandler(i, j) => {
+                let (modifiers, key) = &self.key_handlers[i].alternates[j];
+                (modifiers, key)
+            }
+            Candidate::Prefix(i) => (&self.prefixes[i].modifiers, &self.prefixes[i].key),
+        }
+    }
+
+    /// Returns the index of the prefix that has to be pending for the candidate to match, if any.
+    fn scope(&self, candidate: Candidate) -> Option<usize> {
+        match candidate {
+            Candidate::KeyHandler(i, _) => self.key_handlers[i].prefix,
+            Candidate::Prefix(_) => None,
+        }
+    }
+
+    /// Returns the number that identifies the candidate in the generated code.
+    fn id(&self, candidate: Candidate) -> usize {
+        let alternate_count = |key_handlers: &[KeyHandler]| -> usize {
+            key_handlers
+                .iter()
+                .map(|key_handler| key_handler.alternates.len())
+                .sum()
+        };
+        match candidate {
+            Candidate::KeyHandler(i, j) => alternate_count(&self.key_handlers[..i]) + j,
+            Candidate::Prefix(i) => alternate_count(&self.key_handlers) + i,
+        }
+    }
+
+    /// Extend the token stream with the statement that calls the given method of the key handler
+    /// of the candidate.
+    ///
+    /// A key handler with alternates receives `keyup` only when the last of its held keys
+    /// is released, so it stays pressed while any alternate is held.
+    ///
+    /// A key handler that receives the code is called only on `keydown`. Other key handlers
+    /// receive `event`, which is the `Option<&web_sys::KeyboardEvent>` with the event that is
+    /// being dispatched, if any.
+    ///
+    /// The statement evaluates to whether the key handler handled the event, i.e. to `true`
+    /// if it wasn't called.
+    fn extend_with_key_handler_call(
+        &self,
+        ts: &mut proc_macro2::TokenStream,
+        candidate: Candidate,
+        method: &syn::Ident,
+        event: &proc_macro2::TokenStream,
+    ) {
+        let i = match candidate {
+            Candidate::KeyHandler(i, _) => i,
+            Candidate::Prefix(_) => unreachable!("prefixes have no key handlers"),
+        };
+        let field = Args::ith_field(i);
+        if self.key_handlers[i].receives_code {
+            if method == "handle_keydown" {
+                let variant_path = self.chord(candidate).1.to_variant_path();
+                let outcome_trait_name = self.outcome_trait_name();
+                ts.extend(quote!(#outcome_trait_name::is_handled((self.#field)(#variant_path))));
+            } else {
+                ts.extend(quote!(true));
+            }
+            return;
+        }
+        let call = quote!(Self::dispatch_to_key_handler(#event, || {
+            ::wasm_keyboard::KeyHandler::#method(&self.#field)
+        }));
+        let alternate_count = self.key_handlers[i].alternates.len();
+        if method != "handle_keyup" || alternate_count == 1 {
+            ts.extend(call);
+            return;
+        }
+        let ids = (0..alternate_count).map(|j| self.id(Candidate::KeyHandler(i, j)));
+        ts.extend(quote!(
+            if !self.active_key_handlers.iter().any(|__active_key_handler| {
+                ::core::matches!(__active_key_handler.get(), ::core::option::Option::Some(#(#ids)|*))
+            }) {
+                #call
+            } else {
+                true
+            }
+        ));
+    }
+
+    /// Extend the token stream with the statements that consume the event of the key that
+    /// matches the binding of the candidate, if the binding or the options say so.
+    fn extend_with_consumption(&self, ts: &mut proc_macro2::TokenStream, candidate: Candidate) {
+        let consumption = match candidate {
+            Candidate::KeyHandler(i, _) => &self.key_handlers[i].consumption,
+            Candidate::Prefix(i) => &self.prefixes[i].consumption,
+        };
+        let is_enabled = |binding_value: Option<bool>, option: &Option<syn::LitBool>| {
+            binding_value.unwrap_or_else(|| option.as_ref().map_or(false, |lit| lit.value))
+        };
+        if is_enabled(consumption.prevent_default, &self.options.prevent_default) {
+            ts.extend(quote!(event.prevent_default();));
+        }
+        if is_enabled(consumption.stop_propagation, &self.options.stop_propagation) {
+            ts.extend(quote!(event.stop_propagation();));
+        }
+    }
+
+    /// Extend the token stream with the block that dispatches the event to the candidate.
+    ///
+    /// The event is consumed only if the key handler handles it. When the key handler declines
+    /// the initial `keydown`, the event falls through to the candidates of lower precedence
+    /// that follow it, with `unmatched` evaluated if none of them matches.
+    fn extend_with_candidate_dispatch(
+        &self,
+        ts: &mut proc_macro2::TokenStream,
+        candidate: Candidate,
+        method: &syn::Ident,
+        unmatched: &proc_macro2::TokenStream,
+    ) {
+        let mut consumption = proc_macro2::TokenStream::new();
+        self.extend_with_consumption(&mut consumption, candidate);
+        let is_keydown = method == "handle_keydown";
+        let i = match candidate {
+            Candidate::Prefix(prefix_idx) => {
+                let begin_pending_prefix = if is_keydown {
+                    quote!(if !event.repeat() {
+                        self.begin_pending_prefix(#prefix_idx)
+                    })
+                } else {
+                    quote!()
+                };
+                ts.extend(quote!({
+                    #consumption
+                    #begin_pending_prefix
+                }));
+                return;
+            }
+            Candidate::KeyHandler(i, _) => i,
+        };
+        let mut call = proc_macro2::TokenStream::new();
+        self.extend_with_key_handler_call(
+            &mut call,
+            candidate,
+            method,
+            &quote!(::core::option::Option::Some(event)),
+        );
+        if !is_keydown {
+            ts.extend(quote!(if #call {
+                #consumption
+            }));
+            return;
+        }
+        let key = self.chord(candidate).1;
+        let candidates = self.candidates_for(key, self.scope(candidate));
+        let position = candidates
+            .iter()
+            .position(|&other| self.id(other) == self.id(candidate))
+            .expect("the candidate is among the candidates for its key");
+        let mut fallthrough = proc_macro2::TokenStream::new();
+        self.extend_with_selection_dispatch(
+            &mut fallthrough,
+            &candidates[position + 1..],
+            method,
+            unmatched,
+        );
+        ts.extend(if self.key_handlers[i].receives_code {
+            quote!(
+                // Repeated `keydown` events belong to the initial one, which was handled
+                if event.repeat() || #call {
+                    #consumption
+                } else {
+                    #fallthrough
+                }
+            )
+        } else {
+            quote!(
+                if #call {
+                    #consumption
+                } else if !event.repeat() {
+                    #fallthrough
+                }
+            )
+        });
+    }
+
+    /// Extend the token stream with the block that selects the most specific candidate among
+    /// the given ones, stores it in `__active_key_handler` and dispatches the event to it,
+    /// or evaluates `unmatched` if none of them matches.
+    fn extend_with_selection_dispatch(
+        &self,
+        ts: &mut proc_macro2::TokenStream,
+        candidates: &[Candidate],
+        method: &syn::Ident,
+        unmatched: &proc_macro2::TokenStream,
+    ) {
+        if candidates.is_empty() {
+            ts.extend(quote!({
+                __active_key_handler.set(::core::option::Option::None);
+                #unmatched
+            }));
+            return;
+        }
+        let mut selection = proc_macro2::TokenStream::new();
+        self.extend_with_candidate_selection(&mut selection, candidates);
+        let ids = candidates.iter().map(|&candidate| self.id(candidate));
+        let bodies = candidates.iter().map(|&candidate| {
+            let mut body = proc_macro2::TokenStream::new();
+            self.extend_with_candidate_dispatch(&mut body, candidate, method, unmatched);
+            body
+        });
+        ts.extend(quote!({
+            let __key_handler = #selection;
+            __active_key_handler.set(__key_handler);
+            match __key_handler {
+                #(::core::option::Option::Some(#ids) => #bodies,)*
+                _ => #unmatched,
+            }
+        }));
+    }
+
+    /// Returns the candidates of the key handlers bound to the key, with any prefix.
+    fn key_handler_candidates_for(&self, key: &Key) -> Vec<Candidate> {
+        self.candidates()
+            .filter(|&candidate| {
+                matches!(candidate, Candidate::KeyHandler(..))
+                    && self.chord(candidate).1.code == key.code
+            })
+            .collect()
+    }
+
+    /// Reports every binding (i.e. a key with a set of modifiers) that occurs more than once
+    /// with the same prefix, with an error on each of its entries.
+    ///
+    /// Otherwise, all entries but the first one would be silently unreachable.
+    ///
+    /// The same goes for a binding with `Mod` and the binding with `Ctrl` or `Meta` in its place,
+    /// e.g. `Mod+KEY_K` and `Ctrl+KEY_K`, which coincide on some platforms.
+    fn check_for_duplicate_bindings(&self) -> syn::Result<()> {
+        let mut error: Option<syn::Error> = None;
+        for candidate in self.candidates() {
+            let (modifiers, key) = self.chord(candidate);
+            let others = self.candidates().filter(|&other| {
+                self.id(candidate) != self.id(other)
+                    && self.scope(candidate) == self.scope(other)
+                    && key.code == self.chord(other).1.code
+            });
+            let mut message = None;
+            for other in others {
+                let other_modifiers = self.chord(other).0;
+                if modifiers == other_modifiers {
+                    message = Some(format!("key `{}` is bound more than once", key.name()));
+                    break;
+                }
+                let coincidence = modifiers
+                    .coincidence_with(other_modifiers)
+                    .or_else(|| other_modifiers.coincidence_with(modifiers));
+                if let Some(resolved) = coincidence {
+                    message = Some(format!(
+                        "key `{}` is bound with both `{}` and `{}`, which coincide on {} platforms",
+                        key.name(),
+                        modifiers,
+                        other_modifiers,
+                        if resolved == Modifier::Meta {
+                            "Apple"
+                        } else {
+                            "non-Apple"
+                        }
+                    ));
+                }
+            }
+            let message = match message {
+                Some(message) => message,
+                None => continue,
+            };
+            let e = syn::Error::new(key.span, message);
+            match error {
+                Some(ref mut error) => error.combine(e),
+                None => error = Some(e),
+            }
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
     fn ith_field(i: usize) -> syn::Ident {
         quote::format_ident!("key_handler{}", i)
     }
 
     fn fields(&self) -> impl Iterator<Item = syn::Ident> + Clone {
-        (0..self.key_paths.len()).map(Args::ith_field)
+        (0..self.key_handlers.len()).map(Args::ith_field)
+    }
+
+    fn ith_observer_field(i: usize) -> syn::Ident {
+        quote::format_ident!("observer{}", i)
+    }
+
+    fn observer_fields(&self) -> impl Iterator<Item = syn::Ident> + Clone {
+        (0..self.observers.len()).map(Args::ith_observer_field)
+    }
+
+    /// Returns whether the handler has prefix bindings, and hence the pending state.
+    fn has_prefixes(&self) -> bool {
+        !self.prefixes.is_empty()
+    }
+
+    /// Returns whether some of the key handlers receives the code, i.e. whether
+    /// the outcome trait (see [`Args::outcome_trait_name`]) is needed.
+    fn has_code_receiving_key_handlers(&self) -> bool {
+        self.key_handlers
+            .iter()
+            .any(|key_handler| key_handler.receives_code)
+    }
+
+    /// Returns the name of the trait for the return values of the key handlers that receive
+    /// the code, which tell whether the event was handled, e.g. `KhOutcome`.
+    fn outcome_trait_name(&self) -> syn::Ident {
+        quote::format_ident!("{}Outcome", self.ty_name)
+    }
+
+    /// Returns the type parameters of the keywise keyboard handler along with their bounds:
+    /// `T{i}` for key handlers, `O{i}` for observers, `OnPending` and `OnInvalid` for
+    /// the callbacks of prefixes, `Fallback` for the fallback handler and `R{i}` for
+    /// the return values of the key handlers that receive the code.
+    fn generics_with_bounds(&self) -> Vec<(syn::Ident, proc_macro2::TokenStream)> {
+        let key_handler_generics = self
+            .key_handlers
+            .iter()
+            .enumerate()
+            .map(|(i, key_handler)| {
+                let bound = if key_handler.receives_code {
+                    let return_ty = quote::format_ident!("R{}", i);
+                    quote!(::core::ops::Fn(
+                        ::wasm_keyboard::uievents_code::KeyboardEventCode
+                    ) -> #return_ty)
+                } else {
+                    quote!(::wasm_keyboard::KeyHandler)
+                };
+                (quote::format_ident!("T{}", i), bound)
+            });
+        let outcome_trait_name = self.outcome_trait_name();
+        let outcome_generics = self
+            .key_handlers
+            .iter()
+            .enumerate()
+            .filter(|(_, key_handler)| key_handler.receives_code)
+            .map(|(i, _)| (quote::format_ident!("R{}", i), quote!(#outcome_trait_name)))
+            .collect::<Vec<_>>();
+        let observer_generics = (0..self.observers.len()).map(|i| {
+            (
+                quote::format_ident!("O{}", i),
+                quote!(::core::ops::Fn(&::web_sys::KeyboardEvent)),
+            )
+        });
+        let prefix_generics = if self.has_prefixes() {
+            vec![
+                (
+                    quote::format_ident!("OnPending"),
+                    quote!(::core::ops::Fn(::core::option::Option<&str>)),
+                ),
+                (
+                    quote::format_ident!("OnInvalid"),
+                    quote!(::core::ops::Fn(&str, ::core::option::Option<&str>)),
+                ),
+            ]
+        } else {
+            vec![]
+        };
+        let fallback_generics = self.fallback.iter().map(|_| {
+            (
+                quote::format_ident!("Fallback"),
+                quote!(::core::ops::Fn(&str)),
+            )
+        });
+        key_handler_generics
+            .chain(observer_generics)
+            .chain(prefix_generics)
+            .chain(fallback_generics)
+            .chain(outcome_generics)
+            .collect()
+    }
+
+    /// Returns the type parameters of the keywise keyboard handler.
+    fn generics(&self) -> Vec<syn::Ident> {
+        self.generics_with_bounds()
+            .into_iter()
+            .map(|(ty, _)| ty)
+            .collect()
+    }
+
+    /// Returns the bounds on the type parameters returned by [`Args::generics`].
+    fn where_predicates(&self) -> Vec<proc_macro2::TokenStream> {
+        self.generics_with_bounds()
+            .into_iter()
+            .map(|(ty, bound)| quote!(#ty: #bound + 'static))
+            .collect()
+    }
+
+    /// Returns whether some binding uses the platform-neutral `Mod` modifier.
+    fn has_mod(&self) -> bool {
+        self.candidates()
+            .any(|candidate| self.chord(candidate).0.has_mod())
+    }
+
+    /// Returns the keys of all bindings, in the order of their first occurrence.
+    fn keys(&self) -> Vec<&Key> {
+        let mut keys: Vec<&Key> = Vec::new();
+        for candidate in self.candidates() {
+            let (_, key) = self.chord(candidate);
+            if keys.iter().all(|other| other.code != key.code) {
+                keys.push(key);
+            }
+        }
+        keys
+    }
+
+    /// Returns the candidates bound to the key with the given prefix, sorted from the most
+    /// specific binding to the least specific one.
+    fn candidates_for(&self, key: &Key, prefix: Option<usize>) -> Vec<Candidate> {
+        let mut candidates = self
+            .candidates()
+            .filter(|&candidate| {
+                self.scope(candidate) == prefix && self.chord(candidate).1.code == key.code
+            })
+            .collect::<Vec<_>>();
+        // the sort is stable, so the bindings with equal specificity keep their order
+        candidates.sort_by_key(|&candidate| self.chord(candidate).0.specificity());
+        candidates
+    }
+
+    /// Extend the token stream with the expression that selects the identifier of the most
+    /// specific candidate among the given ones whose modifiers match `__modifier_states`.
+    fn extend_with_candidate_selection(
+        &self,
+        ts: &mut proc_macro2::TokenStream,
+        candidates: &[Candidate],
+    ) {
+        let modifier_states = quote::format_ident!("__modifier_states");
+        let conditions = candidates.iter().map(|&candidate| {
+            let mut condition = proc_macro2::TokenStream::new();
+            self.chord(candidate).0.extend_with_condition(
+                &mut condition,
+                &modifier_states,
+                &quote!(self.mod_index),
+            );
+            condition
+        });
+        let ids = candidates.iter().map(|&candidate| self.id(candidate));
+        ts.extend(quote!(
+            #(if #conditions { ::core::option::Option::Some(#ids) } else)*
+            { ::core::option::Option::None }
+        ));
+    }
+
+    /// Extend the token stream with the statement that handles the `keydown` event while
+    /// a prefix is pending, i.e. either dispatches it to the key handler bound to the key
+    /// with the pending prefix or reports that the prefix wasn't continued.
+    fn extend_with_pending_prefix_handling(&self, ts: &mut proc_macro2::TokenStream) {
+        let keys = self.keys();
+        let arms = (0..self.prefixes.len()).flat_map(|prefix_idx| {
+            keys.iter().enumerate().filter_map(move |(key_idx, key)| {
+                let candidates = self.candidates_for(key, Some(prefix_idx));
+                if candidates.is_empty() {
+                    return None;
+                }
+                let code_lit = key.to_code_lit();
+                let mut dispatch = proc_macro2::TokenStream::new();
+                self.extend_with_selection_dispatch(
+                    &mut dispatch,
+                    &candidates,
+                    &quote::format_ident!("handle_keydown"),
+                    &quote!((self.on_invalid)(
+                        Self::PREFIXES[__prefix],
+                        ::core::option::Option::Some(__code.as_str()),
+                    )),
+                );
+                Some(quote!(
+                    (#prefix_idx, #code_lit) => {
+                        self.end_pending_prefix();
+                        let __active_key_handler = &self.active_key_handlers[#key_idx];
+                        #dispatch
+                    }
+                ))
+            })
+        });
+        let modifier_key_codes = MODIFIER_KEY_CODES;
+        ts.extend(quote!(
+            if let ::core::option::Option::Some(__prefix) = self.pending_prefix.get() {
+                // The key of the prefix may still be held
+                if event.repeat() {
+                    return;
+                }
+                match (__prefix, __code.as_str()) {
+                    #(#arms)*
+                    // The modifiers of the next chord are pressed before its key
+                    (_, #(#modifier_key_codes)|*) => (),
+                    (_, "Escape") => {
+                        self.end_pending_prefix();
+                        (self.on_invalid)(Self::PREFIXES[__prefix], ::core::option::Option::None);
+                    }
+                    _ => {
+                        self.end_pending_prefix();
+                        (self.on_invalid)(Self::PREFIXES[__prefix], ::core::option::Option::Some(__code.as_str()));
+                    }
+                }
+                return;
+            }
+        ));
+    }
+
+    /// Extend the token stream with the methods that enter and leave the pending state.
+    fn extend_with_pending_prefix_methods(&self, ts: &mut proc_macro2::TokenStream) {
+        let prefix_count = self.prefixes.len();
+        let descriptions = self.prefixes.iter().map(Prefix::description);
+        let (timeout_start, timeout_cancel) = if self.options.prefix_timeout.is_some() {
+            let timeout_start = quote!(
+                let __generation = self.prefix_generation.get();
+                let __this = self.this.clone();
+                let __callback = ::wasm_bindgen::closure::Closure::once_into_js(move || {
+                    let __this = match __this.upgrade() {
+                        ::core::option::Option::Some(__this) => __this,
+                        // The keyboard handler is already dropped
+                        ::core::option::Option::None => return,
+                    };
+                    if __this.prefix_generation.get() != __generation {
+                        return;
+                    }
+                    if let ::core::option::Option::Some(__prefix) = __this.pending_prefix.get() {
+                        __this.end_pending_prefix();
+                        (__this.on_invalid)(Self::PREFIXES[__prefix], ::core::option::Option::None);
+                    }
+                });
+                if let ::core::option::Option::Some(__window) = ::web_sys::window() {
+                    // Without the timer, the prefix stays pending until the next key is pressed
+                    let _ = __window.set_timeout_with_callback_and_timeout_and_arguments_0(
+                        ::wasm_bindgen::JsCast::unchecked_ref(&__callback),
+                        self.prefix_timeout,
+                    );
+                }
+            );
+            // Makes the timers of the prefixes that are no longer pending do nothing
+            let timeout_cancel = quote!(
+                self.prefix_generation.set(self.prefix_generation.get().wrapping_add(1));
+            );
+            (timeout_start, timeout_cancel)
+        } else {
+            (quote!(), quote!())
+        };
+        ts.extend(quote!(
+            /// Human-readable descriptions of the prefixes, e.g. `Ctrl+KeyX`.
+            const PREFIXES: [&'static str; #prefix_count] = [#(#descriptions),*];
+
+            fn begin_pending_prefix(&self, prefix: usize) {
+                self.pending_prefix.set(::core::option::Option::Some(prefix));
+                (self.on_pending)(::core::option::Option::Some(Self::PREFIXES[prefix]));
+                #timeout_start
+            }
+
+            fn end_pending_prefix(&self) {
+                self.pending_prefix.set(::core::option::Option::None);
+                #timeout_cancel
+                (self.on_pending)(::core::option::Option::None);
+            }
+        ));
     }
 
     /// Extend the token stream with the [items] that are needed for the keyboard handler,
@@ -92,102 +1043,910 @@ impl Args {
     ///
     /// [items]: https://doc.rust-lang.org/reference/items.html
     fn extend_with_items(&self, ts: &mut proc_macro2::TokenStream) {
-        let &Self {
-            ref ty_name,
-            ref key_paths,
-            ..
-        } = self;
+        let Self { ty_name, .. } = self;
 
-        let generics = (0..key_paths.len()).map(|i| quote::format_ident!("T{}", i));
-        let [generics_clone0, generics_clone1, generics_clone2, generics_clone3] =
-            std::array::from_fn(|_i| generics.clone());
-        let field_ty_pairs = (0..key_paths.len()).map(|i| {
-            let field = Args::ith_field(i);
-            let ty = quote::format_ident!("T{}", i);
-            quote!(#field: #ty)
-        });
-        let fields = self.fields();
+        let generics = self.generics();
+        let where_predicates = self.where_predicates();
+        let fields = self.fields().chain(self.observer_fields());
+        let field_ty_pairs = fields
+            .zip(generics.iter())
+            .map(|(field, ty)| quote!(#field: #ty));
+        let observer_fields = self.observer_fields();
+        let keys = self.keys();
+        let key_count = keys.len();
 
         let method_decls: [proc_macro2::TokenStream; EVENT_COUNT] = EVENTS.map(|event| {
+            // The fallback handler is called once per press of the key that matches no binding
+            let unmatched = if event == "keydown" && self.fallback.is_some() {
+                quote!(if !event.repeat() {
+                    (self.fallback)(__code.as_str())
+                })
+            } else {
+                quote!(())
+            };
             let keyboard_handler_impl_method = quote::format_ident!("inner_handle_{event}");
             let key_handler_impl_method = quote::format_ident!("handle_{event}");
-            let fields = fields.clone();
-            // TODO: eventually optimize match with alternatives
+            let arms = keys.iter().enumerate().map(|(key_idx, key)| {
+                let code_lit = key.to_code_lit();
+                let mut selection = proc_macro2::TokenStream::new();
+                self.extend_with_candidate_selection(
+                    &mut selection,
+                    &self.candidates_for(key, None),
+                );
+                // While the key is held, its events are routed to the key handler that
+                // received the initial `keydown`, even if the modifiers change.
+                let active_key_handler = match event {
+                    "keydown" => quote!({
+                        let __key_handler = __active_key_handler.get().or_else(|| #selection);
+                        __active_key_handler.set(__key_handler);
+                        __key_handler
+                    }),
+                    "keyup" => quote!(__active_key_handler.take().or_else(|| #selection)),
+                    _ => quote!(__active_key_handler.get().or_else(|| #selection)),
+                };
+                // The key handlers bound with a prefix receive the rest of the events
+                // of the key as well
+                let prefix_candidates = self
+                    .candidates_for(key, None)
+                    .into_iter()
+                    .filter(|candidate| matches!(candidate, Candidate::Prefix(_)));
+                let candidates = self
+                    .key_handler_candidates_for(key)
+                    .into_iter()
+                    .chain(prefix_candidates)
+                    .collect::<Vec<_>>();
+                let ids = candidates.iter().map(|&candidate| self.id(candidate));
+                let bodies = candidates.iter().map(|&candidate| {
+                    let mut body = proc_macro2::TokenStream::new();
+                    self.extend_with_candidate_dispatch(
+                        &mut body,
+                        candidate,
+                        &key_handler_impl_method,
+                        &unmatched,
+                    );
+                    body
+                });
+                quote!(
+                    #code_lit => {
+                        let __active_key_handler = &self.active_key_handlers[#key_idx];
+                        match #active_key_handler {
+                            #(::core::option::Option::Some(#ids) => #bodies,)*
+                            _ => #unmatched,
+                        }
+                    }
+                )
+            });
+            let mut modifier_states = proc_macro2::TokenStream::new();
+            Modifiers::extend_with_modifier_states(
+                &mut modifier_states,
+                &quote::format_ident!("event"),
+            );
+            let observer_fields = observer_fields.clone();
+            let mut pending_prefix_handling = proc_macro2::TokenStream::new();
+            if event == "keydown" && self.has_prefixes() {
+                self.extend_with_pending_prefix_handling(&mut pending_prefix_handling);
+            }
             quote!(
                 fn #keyboard_handler_impl_method(&self, event: &::web_sys::KeyboardEvent) {
-                    match event.code().as_str() {
-                        #(#key_paths => self.#fields.#key_handler_impl_method()),*,
-                        _ => (),
+                    #((self.#observer_fields)(event);)*
+                    let __modifier_states: [bool; 4] = #modifier_states;
+                    let __code = event.code();
+                    #pending_prefix_handling
+                    match __code.as_str() {
+                        #(#arms)*
+                        _ => #unmatched,
                     }
                 }
             )
         });
 
+        let mod_index_field = if self.has_mod() {
+            quote!(
+                /// Index of the modifier that `Mod` resolves to in `[ctrl, shift, alt, meta]`.
+                mod_index: usize,
+            )
+        } else {
+            quote!()
+        };
+        let mut pending_prefix_methods = proc_macro2::TokenStream::new();
+        let pending_prefix_fields = if self.has_prefixes() {
+            self.extend_with_pending_prefix_methods(&mut pending_prefix_methods);
+            let timeout_fields = if self.options.prefix_timeout.is_some() {
+                quote!(
+                    /// The keyboard handler itself, for the timers that abandon the pending prefix.
+                    this: ::std::rc::Weak<Self>,
+                    /// Incremented whenever the pending prefix changes, so that the timers
+                    /// of the previous prefixes don't abandon the current one.
+                    prefix_generation: ::core::cell::Cell<u32>,
+                    /// The number of milliseconds after which a pending prefix is abandoned.
+                    prefix_timeout: i32,
+                )
+            } else {
+                quote!()
+            };
+            quote!(
+                /// Index of the prefix that was pressed and awaits the next key, if any.
+                pending_prefix: ::core::cell::Cell<::core::option::Option<usize>>,
+                on_pending: OnPending,
+                on_invalid: OnInvalid,
+                #timeout_fields
+            )
+        } else {
+            quote!()
+        };
+
+        let fallback_field = if self.fallback.is_some() {
+            quote!(fallback: Fallback,)
+        } else {
+            quote!()
+        };
+        let release_pending_prefix = if self.has_prefixes() {
+            quote!(
+                if let ::core::option::Option::Some(__prefix) = self.pending_prefix.get() {
+                    self.end_pending_prefix();
+                    (self.on_invalid)(Self::PREFIXES[__prefix], ::core::option::Option::None);
+                }
+            )
+        } else {
+            quote!()
+        };
+        let key_handler_candidates = self
+            .candidates()
+            .filter(|candidate| matches!(candidate, Candidate::KeyHandler(..)))
+            .collect::<Vec<_>>();
+        let ids = key_handler_candidates
+            .iter()
+            .map(|&candidate| self.id(candidate));
+        let calls = key_handler_candidates.iter().map(|&candidate| {
+            let mut call = proc_macro2::TokenStream::new();
+            self.extend_with_key_handler_call(
+                &mut call,
+                candidate,
+                &quote::format_ident!("handle_keyup"),
+                &quote!(::core::option::Option::None),
+            );
+            call
+        });
+        let release_method = quote!(
+            /// Sends a synthetic `keyup` to the key handlers of the keys that are currently held
+            /// and abandons the pending prefix, if any.
+            ///
+            /// This is useful when `keyup` events can be lost, e.g. when the page loses focus.
+            #[allow(dead_code)]
+            fn release_pressed_keys(&self) {
+                for __active_key_handler in self.active_key_handlers.iter() {
+                    match __active_key_handler.take() {
+                        #(::core::option::Option::Some(#ids) => {
+                            #calls;
+                        })*
+                        _ => (),
+                    }
+                }
+                #release_pending_prefix
+            }
+        );
+
+        let dispatch_to_key_handler_fn = if self
+            .key_handlers
+            .iter()
+            .any(|key_handler| !key_handler.receives_code)
+        {
+            let mut dispatch_to_key_handler_fn = proc_macro2::TokenStream::new();
+            extend_with_dispatch_to_key_handler_fn(&mut dispatch_to_key_handler_fn);
+            dispatch_to_key_handler_fn
+        } else {
+            quote!()
+        };
+
+        if self.has_code_receiving_key_handlers() {
+            extend_with_outcome_trait(ts, &self.outcome_trait_name());
+        }
+
         ts.extend(quote!(
         struct #ty_name<#(#generics),*>
         where
-            #(#generics_clone0: ::wasm_keyboard::KeyHandler),*
+            #(#where_predicates),*
         {
-            #(#field_ty_pairs),*
+            #(#field_ty_pairs,)*
+            /// For each bound key, the index of the key handler that received the initial
+            /// `keydown` of the key that is currently held.
+            active_key_handlers: [::core::cell::Cell<::core::option::Option<usize>>; #key_count],
+            #mod_index_field
+            #pending_prefix_fields
+            #fallback_field
         }
 
-        impl<#(#generics_clone1),*> #ty_name<#(#generics_clone2),*>
+        impl<#(#generics),*> #ty_name<#(#generics),*>
         where
-            #(#generics_clone3: ::wasm_keyboard::KeyHandler),*
+            #(#where_predicates),*
         {
             #(#method_decls)*
+            #release_method
+            #pending_prefix_methods
+            #dispatch_to_key_handler_fn
         }));
     }
 
-    fn extend_with_var_binding(&self, ts: &mut proc_macro2::TokenStream) {
-        let &Self {
-            ref instance_name,
-            ref ty_name,
-            ref key_handlers_exprs,
-            ..
-        } = self;
+    fn guard_ty_name(&self) -> syn::Ident {
+        quote::format_ident!("{}Guard", self.ty_name)
+    }
 
-        let fields = self.fields();
+    /// Extend the token stream with the [items] for the guard that owns the event listeners
+    /// and removes them from the target when dropped.
+    ///
+    /// [items]: https://doc.rust-lang.org/reference/items.html
+    fn extend_with_guard_items(&self, ts: &mut proc_macro2::TokenStream) {
+        let Self { ty_name, .. } = self;
+
+        let guard_ty_name = self.guard_ty_name();
+        let events = EVENTS;
+        let generics = self.generics();
+        let where_predicates = self.where_predicates();
+        let (listener_options_fields, add_listener, remove_listener) =
+            if self.options.listener_options().is_empty() {
+                (
+                    quote!(),
+                    quote!(add_event_listener_with_callback(
+                        event,
+                        ::wasm_bindgen::JsCast::unchecked_ref(listener.as_ref()),
+                    )),
+                    quote!(remove_event_listener_with_callback(
+                        event,
+                        ::wasm_bindgen::JsCast::unchecked_ref(listener.as_ref()),
+                    )),
+                )
+            } else {
+                (
+                    quote!(
+                        listener_options: ::web_sys::AddEventListenerOptions,
+                        /// The listeners added in the capture phase have to be removed
+                        /// in the capture phase as well.
+                        capture: bool,
+                    ),
+                    quote!(
+                        add_event_listener_with_callback_and_add_event_listener_options(
+                            event,
+                            ::wasm_bindgen::JsCast::unchecked_ref(listener.as_ref()),
+                            &self.listener_options,
+                        )
+                    ),
+                    quote!(remove_event_listener_with_callback_and_bool(
+                        event,
+                        ::wasm_bindgen::JsCast::unchecked_ref(listener.as_ref()),
+                        self.capture,
+                    )),
+                )
+            };
 
         ts.extend(quote!(
-            let #instance_name = ::std::rc::Rc::new(#ty_name {
-                #( #fields: #key_handlers_exprs ),*
-            });
-        ))
+        /// Guard that owns the event listeners of the keywise keyboard handler.
+        ///
+        /// The listeners are removed from the target when the guard is dropped.
+        #[must_use = "dropping the guard detaches the listeners"]
+        struct #guard_ty_name<#(#generics),*>
+        where
+            #(#where_predicates),*
+        {
+            handler: ::std::rc::Rc<#ty_name<#(#generics),*>>,
+            target: ::web_sys::EventTarget,
+            listeners: [::wasm_bindgen::closure::Closure<dyn ::core::ops::FnMut(::web_sys::KeyboardEvent)>; #EVENT_COUNT],
+            #listener_options_fields
+            /// Target whose events release the keys that are currently held, if any.
+            blur_target: ::core::option::Option<::web_sys::EventTarget>,
+            release_listener: ::wasm_bindgen::closure::Closure<dyn ::core::ops::FnMut(::web_sys::Event)>,
+        }
+
+        impl<#(#generics),*> #guard_ty_name<#(#generics),*>
+        where
+            #(#where_predicates),*
+        {
+            const EVENTS: [&'static str; #EVENT_COUNT] = [#(#events),*];
+            /// Events after which `keyup` events of the keys that are currently held
+            /// don't arrive.
+            const RELEASE_EVENTS: [&'static str; 2] = ["blur", "visibilitychange"];
+
+            /// Adds the event listeners to the target.
+            ///
+            /// Stops at the first failure, leaving the removal of the listeners
+            /// that were already added to [`Drop`].
+            fn attach(&self) -> ::core::result::Result<(), ::wasm_bindgen::JsValue> {
+                for (event, listener) in Self::EVENTS.iter().zip(self.listeners.iter()) {
+                    self.target.#add_listener?;
+                }
+                if let ::core::option::Option::Some(blur_target) = &self.blur_target {
+                    for event in Self::RELEASE_EVENTS.iter() {
+                        blur_target.add_event_listener_with_callback(
+                            event,
+                            ::wasm_bindgen::JsCast::unchecked_ref(self.release_listener.as_ref()),
+                        )?;
+                    }
+                }
+                Ok(())
+            }
+
+            /// Removes the event listeners from the target.
+            #[allow(dead_code)]
+            fn detach(self) {}
+
+            /// Keeps the event listeners attached for the rest of the lifetime of the page.
+            #[allow(dead_code)]
+            fn forget(self) {
+                ::core::mem::forget(self)
+            }
+        }
+
+        impl<#(#generics),*> ::core::ops::Deref for #guard_ty_name<#(#generics),*>
+        where
+            #(#where_predicates),*
+        {
+            type Target = #ty_name<#(#generics),*>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.handler
+            }
+        }
+
+        impl<#(#generics),*> ::core::ops::Drop for #guard_ty_name<#(#generics),*>
+        where
+            #(#where_predicates),*
+        {
+            fn drop(&mut self) {
+                for (event, listener) in Self::EVENTS.iter().zip(self.listeners.iter()) {
+                    // Removal can fail only if the target is no longer a valid `EventTarget`,
+                    // in which case the listener is gone anyway.
+                    let _ = self.target.#remove_listener;
+                }
+                if let ::core::option::Option::Some(blur_target) = &self.blur_target {
+                    for event in Self::RELEASE_EVENTS.iter() {
+                        let _ = blur_target.remove_event_listener_with_callback(
+                            event,
+                            ::wasm_bindgen::JsCast::unchecked_ref(self.release_listener.as_ref()),
+                        );
+                    }
+                }
+            }
+        }));
     }
 
-    fn extend_with_code_adding_listeners(&self, ts: &mut proc_macro2::TokenStream) {
-        let &Self {
-            ref instance_name,
-            ref target,
+    fn extend_with_var_binding(&self, ts: &mut proc_macro2::TokenStream) {
+        let Self {
+            fallible,
+            instance_name,
+            ty_name,
+            target,
+            key_handlers,
+            observers,
             ..
         } = self;
 
-        for block in EVENTS.map(|event| {
+        let key_handlers_exprs = key_handlers
+            .iter()
+            .map(|key_handler| &key_handler.key_handler_expr);
+        let observer_fields = self.observer_fields();
+        let guard_ty_name = self.guard_ty_name();
+        let fields = self.fields();
+        let mod_index_init = if self.has_mod() {
+            let mut mod_index = proc_macro2::TokenStream::new();
+            Modifiers::extend_with_mod_index(&mut mod_index, self.options.platform.as_ref());
+            quote!(mod_index: #mod_index,)
+        } else {
+            quote!()
+        };
+        let pending_prefix_init = if self.has_prefixes() {
+            let on_pending = match &self.options.on_pending {
+                Some(on_pending) => quote!(#on_pending),
+                None => quote!(|_| ()),
+            };
+            let on_invalid = match &self.options.on_invalid {
+                Some(on_invalid) => quote!(#on_invalid),
+                None => quote!(|_, _| ()),
+            };
+            let timeout_init = match &self.options.prefix_timeout {
+                Some(prefix_timeout) => quote!(
+                    this: __this.clone(),
+                    prefix_generation: ::core::cell::Cell::new(0),
+                    prefix_timeout: (#prefix_timeout) as i32,
+                ),
+                None => quote!(),
+            };
+            quote!(
+                pending_prefix: ::core::cell::Cell::new(::core::option::Option::None),
+                on_pending: {
+                    // Helps the type inference of the closure parameter
+                    fn constrain<F: ::core::ops::Fn(::core::option::Option<&str>)>(f: F) -> F {
+                        f
+                    }
+                    constrain(#on_pending)
+                },
+                on_invalid: {
+                    fn constrain<F: ::core::ops::Fn(&str, ::core::option::Option<&str>)>(f: F) -> F {
+                        f
+                    }
+                    constrain(#on_invalid)
+                },
+                #timeout_init
+            )
+        } else {
+            quote!()
+        };
+        let listeners = EVENTS.map(|event| {
             let keyboard_handler_impl_method = quote::format_ident!("inner_handle_{event}");
             quote!(
                 {
-                    let #instance_name = #instance_name.clone();
-                    let __handler = ::wasm_bindgen::closure::Closure::<dyn ::core::ops::FnMut(_)>::new::<_>(
-                        move |event: ::web_sys::KeyboardEvent| #instance_name.#keyboard_handler_impl_method(&event),
-                    );
-                    #target
-                        .add_event_listener_with_callback(
-                            #event,
-                            ::wasm_bindgen::JsCast::unchecked_ref(__handler.as_ref()),
-                        )
-                        .unwrap();
-                    ::wasm_bindgen::closure::Closure::forget(__handler);
+                    let __handler = __handler.clone();
+                    ::wasm_bindgen::closure::Closure::<dyn ::core::ops::FnMut(_)>::new::<_>(
+                        move |event: ::web_sys::KeyboardEvent| __handler.#keyboard_handler_impl_method(&event),
+                    )
                 }
             )
-        }) {
-            ts.extend(block);
-        }
+        });
+
+        let fallback_init = match &self.fallback {
+            Some(fallback) => quote!(
+                fallback: {
+                    // Helps the type inference of the closure parameter
+                    fn constrain<F: ::core::ops::Fn(&str)>(f: F) -> F {
+                        f
+                    }
+                    constrain(#fallback)
+                },
+            ),
+            None => quote!(),
+        };
+        let blur_target = match &self.options.blur_target {
+            Some(blur_target) => quote!(::core::option::Option::Some({
+                let __blur_target: &::web_sys::EventTarget = &(#blur_target);
+                __blur_target.clone()
+            })),
+            None => quote!(
+                ::web_sys::window().map(::core::convert::Into::<::web_sys::EventTarget>::into)
+            ),
+        };
+        let release_init = quote!(
+            blur_target: #blur_target,
+            release_listener: {
+                let __handler = __handler.clone();
+                ::wasm_bindgen::closure::Closure::<dyn ::core::ops::FnMut(_)>::new::<_>(
+                    move |_: ::web_sys::Event| __handler.release_pressed_keys(),
+                )
+            },
+        );
+        let listener_options = self.options.listener_options();
+        let (listener_options_decl, listener_options_init) = if listener_options.is_empty() {
+            (quote!(), quote!())
+        } else {
+            let capture = match &self.options.capture {
+                Some(capture) => quote!(#capture),
+                None => quote!(false),
+            };
+            // `capture` is evaluated only once because it's also needed to remove the listeners
+            let setters = listener_options.iter().map(|(name, value)| {
+                if name == "capture" {
+                    quote!(__listener_options.capture(__capture);)
+                } else {
+                    quote!(__listener_options.#name(#value);)
+                }
+            });
+            (
+                quote!(
+                    let __capture: bool = #capture;
+                    let mut __listener_options = ::web_sys::AddEventListenerOptions::new();
+                    // The setters are deprecated in favor of `set_*` ones only in newer versions
+                    // of `web_sys`
+                    #[allow(deprecated)]
+                    {
+                        #(#setters)*
+                    }
+                ),
+                quote!(
+                    listener_options: __listener_options,
+                    capture: __capture,
+                ),
+            )
+        };
+        let guard = quote!(
+            {
+                let __handler = ::std::rc::Rc::new_cyclic(|__this| #ty_name {
+                    #( #fields: #key_handlers_exprs, )*
+                    #( #observer_fields: #observers, )*
+                    active_key_handlers: ::core::array::from_fn(|_| ::core::cell::Cell::new(::core::option::Option::None)),
+                    #mod_index_init
+                    #pending_prefix_init
+                    #fallback_init
+                });
+                let __target: &::web_sys::EventTarget = &(#target);
+                #listener_options_decl
+                let __guard = #guard_ty_name {
+                    handler: __handler.clone(),
+                    target: __target.clone(),
+                    listeners: [#(#listeners),*],
+                    #listener_options_init
+                    #release_init
+                };
+                match __guard.attach() {
+                    ::core::result::Result::Ok(()) => ::core::result::Result::Ok(__guard),
+                    // Dropping the guard removes the listeners that were already added
+                    ::core::result::Result::Err(e) => ::core::result::Result::Err(e),
+                }
+            }
+        );
+
+        ts.extend(if *fallible {
+            quote!(let #instance_name: ::core::result::Result<_, ::wasm_bindgen::JsValue> = #guard;)
+        } else {
+            quote!(let #instance_name = #guard.unwrap();)
+        })
     }
 
     pub(super) fn extend_with_startup_code(&self, ts: &mut proc_macro2::TokenStream) {
         self.extend_with_items(ts);
+        self.extend_with_guard_items(ts);
         self.extend_with_var_binding(ts);
-        self.extend_with_code_adding_listeners(ts);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(bindings: &str) -> String {
+        let input = format!("kh: Kh, document, [{}]", bindings);
+        match syn::parse_str::<Args>(&input) {
+            Ok(_) => panic!("`{}` is expected to be rejected", bindings),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn options_error(options: &str) -> String {
+        let input = format!("kh: Kh, document, [KEY_A => a], {}", options);
+        match syn::parse_str::<Args>(&input) {
+            Ok(_) => panic!("`{}` is expected to be rejected", options),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn startup_code(input: &str) -> String {
+        let mut ts = proc_macro2::TokenStream::new();
+        syn::parse_str::<Args>(input)
+            .unwrap()
+            .extend_with_startup_code(&mut ts);
+        ts.to_string()
+    }
+
+    #[test]
+    fn duplicate_bindings_are_rejected() {
+        assert_eq!(
+            parse_error("KEY_A => a, KEY_A => b"),
+            "key `KeyA` is bound more than once"
+        );
+        assert_eq!(
+            parse_error("KEY_A | Shift+KEY_B => a, Shift+KEY_B => b"),
+            "key `KeyB` is bound more than once"
+        );
+        assert_eq!(
+            parse_error("Digit0..=Digit9 => |code| select(code), DIGIT5 => b"),
+            "key `Digit5` is bound more than once"
+        );
+        assert_eq!(
+            parse_error("Ctrl+KEY_X => [KEY_A => a, KEY_A => b]"),
+            "key `KeyA` is bound more than once"
+        );
+    }
+
+    #[test]
+    fn same_key_with_other_modifiers_or_prefix_is_accepted() {
+        let input = "kh: Kh, document, [KEY_A => a, Shift+KEY_A => b, Ctrl+KEY_X => [KEY_A => c]]";
+        assert!(syn::parse_str::<Args>(input).is_ok());
+    }
+
+    #[test]
+    fn mod_and_ctrl_bindings_of_the_same_key_are_rejected() {
+        assert_eq!(
+            parse_error("Mod+KEY_K => a, Ctrl+KEY_K => b"),
+            "key `KeyK` is bound with both `Mod+` and `Ctrl+`, which coincide on non-Apple platforms"
+        );
+        assert_eq!(
+            parse_error("Meta+Shift+KEY_K => a, Mod+Shift+KEY_K => b"),
+            "key `KeyK` is bound with both `Shift+Meta+` and `Shift+Mod+`, which coincide on Apple platforms"
+        );
+    }
+
+    #[test]
+    fn mod_and_ctrl_bindings_of_different_keys_are_accepted() {
+        let input = "kh: Kh, document, [Mod+KEY_K => a, Ctrl+KEY_J => b, Mod+Alt+KEY_J => c]";
+        assert!(syn::parse_str::<Args>(input).is_ok());
+    }
+
+    #[test]
+    fn only_closures_of_key_ranges_and_groups_receive_the_code() {
+        let receives_code =
+            |binding: &str| syn::parse_str::<KeyHandler>(binding).unwrap().receives_code;
+        assert!(receives_code("Digit0..=Digit9 => |code| select(code)"));
+        assert!(receives_code("KEY_Q | Numpad* => move |code| select(code)"));
+        assert!(!receives_code("Digit0..=Digit9 => digit_handler"));
+        assert!(!receives_code("Numpad* => handlers.numpad.clone()"));
+        assert!(!receives_code("KEY_Q => |code| select(code)"));
+    }
+
+    #[test]
+    fn unknown_alternates_are_rejected_only_with_closures() {
+        assert_eq!(
+            parse_error("MY_KEY | Digit0..=Digit9 => |code| select(code)"),
+            "unknown key code `MY_KEY`, the closures of key ranges and groups require known key codes"
+        );
+        let input = "kh: Kh, document, [my_keys::JUMP | Digit0..=Digit9 => handler]";
+        assert!(syn::parse_str::<Args>(input).is_ok());
+    }
+
+    #[test]
+    fn keywise_keyboard_handlers_can_share_a_scope() {
+        let items = |input: &str| {
+            let mut ts = proc_macro2::TokenStream::new();
+            syn::parse_str::<Args>(input)
+                .unwrap()
+                .extend_with_startup_code(&mut ts);
+            let block = syn::parse2::<syn::Block>(quote!({ #ts })).unwrap();
+            block
+                .stmts
+                .into_iter()
+                .filter_map(|stmt| match stmt {
+                    syn::Stmt::Item(item) => Some(item),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let names = |items: &[syn::Item]| {
+            items
+                .iter()
+                .filter_map(|item| match item {
+                    syn::Item::Struct(item) => Some(item.ident.to_string()),
+                    syn::Item::Trait(item) => Some(item.ident.to_string()),
+                    syn::Item::Fn(item) => Some(item.sig.ident.to_string()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let kh =
+            items("kh: Kh, document, [KEY_A => a, KEY_L => long_press, Digit0..=Digit9 => |_| ()]");
+        let kh2 = items("kh2: Kh2, document, [KEY_B => b, KEY_M => multi_tap]");
+        let kh2_names = names(&kh2);
+        assert!(names(&kh).iter().all(|name| !kh2_names.contains(name)));
+        // A blanket implementation would make the method calls of the key handlers ambiguous
+        let is_blanket_impl = |item: &syn::Item| {
+            match item {
+            syn::Item::Impl(item) => item.generics.type_params().any(|param| {
+                matches!(&*item.self_ty, syn::Type::Path(ty) if ty.path.is_ident(&param.ident))
+            }),
+            _ => false,
+        }
+        };
+        assert!(!kh.iter().chain(&kh2).any(is_blanket_impl));
+    }
+
+    #[test]
+    fn held_keys_are_released_on_blur_of_the_window_by_default() {
+        let startup_code = startup_code("kh: Kh, document, [KEY_A => a]");
+        assert!(startup_code
+            .contains("blur_target : :: web_sys :: window () . map (:: core :: convert :: Into"));
+        assert!(startup_code.contains(
+            "const RELEASE_EVENTS : [& 'static str ; 2] = [\"blur\" , \"visibilitychange\"]"
+        ));
+        // The held keys receive a synthetic `keyup` without the event
+        assert!(startup_code.contains(
+            "Self :: dispatch_to_key_handler (:: core :: option :: Option :: None , || { \
+            :: wasm_keyboard :: KeyHandler :: handle_keyup (& self . key_handler0) })"
+        ));
+    }
+
+    #[test]
+    fn blur_target_option_replaces_the_window() {
+        let startup_code =
+            startup_code("kh: Kh, document, [KEY_A => a], blur_target = canvas.clone()");
+        assert!(startup_code.contains(
+            "blur_target : :: core :: option :: Option :: Some ({ \
+            let __blur_target : & :: web_sys :: EventTarget = & (canvas . clone ())"
+        ));
+        assert!(!startup_code.contains(":: web_sys :: window () . map"));
+        assert_eq!(
+            options_error("blur_target = a, blur_target = b"),
+            "option `blur_target` is specified more than once"
+        );
+        assert!(options_error("blur = a").starts_with("unknown option `blur`"));
+    }
+
+    #[test]
+    fn fallback_binding_is_parsed() {
+        let args = syn::parse_str::<Args>("kh: Kh, document, [KEY_A => a, _ => |code| log(code)]")
+            .unwrap();
+        let fallback = args.fallback.as_ref().unwrap();
+        assert_eq!(quote!(#fallback).to_string(), "| code | log (code)");
+        // The fallback alone is a valid list of bindings
+        assert!(syn::parse_str::<Args>("kh: Kh, document, [_ => fallback]").is_ok());
+        assert_eq!(
+            parse_error("_ => a, KEY_A => b, _ => c"),
+            "fallback handler is provided more than once"
+        );
+        assert_eq!(
+            parse_error("#[prevent_default] _ => a"),
+            "only key handlers and prefixes can have attributes"
+        );
+    }
+
+    #[test]
+    fn fallback_is_called_once_per_press_of_an_unmatched_key() {
+        let with_fallback = startup_code("kh: Kh, document, [KEY_A => a, _ => fallback]");
+        let unmatched = "if ! event . repeat () { (self . fallback) (__code . as_str ()) }";
+        assert!(with_fallback.contains("fallback : Fallback ,"));
+        assert!(with_fallback.contains("Fallback : :: core :: ops :: Fn (& str) + 'static"));
+        // Unlike `keydown`, the rest of the events of the unmatched keys are ignored
+        let (keydown, rest) = with_fallback.split_once("fn inner_handle_keyup").unwrap();
+        assert!(keydown.contains(unmatched));
+        assert!(!rest.contains(unmatched));
+        assert!(!startup_code("kh: Kh, document, [KEY_A => a]").contains("self . fallback"));
+    }
+
+    #[test]
+    fn alternates_share_a_key_handler() {
+        let key_handler = syn::parse_str::<KeyHandler>("KEY_W | Shift+ARROW_UP => up").unwrap();
+        let alternates = key_handler
+            .alternates
+            .iter()
+            .map(|(modifiers, key)| format!("{}{}", modifiers, key.name()))
+            .collect::<Vec<_>>();
+        assert_eq!(alternates, ["KeyW", "Shift+ArrowUp"]);
+        assert_eq!(
+            parse_error("Ctrl+KEY_X | Ctrl+KEY_Y => [KEY_S => save]"),
+            "prefixes cannot have alternates"
+        );
+    }
+
+    #[test]
+    fn key_handler_with_alternates_is_released_with_the_last_held_key() {
+        let is_held = "self . active_key_handlers . iter () . any (| __active_key_handler | { \
+            :: core :: matches ! (__active_key_handler . get () , \
+            :: core :: option :: Option :: Some (0usize | 1usize)) })";
+        assert!(startup_code("kh: Kh, document, [KEY_W | ARROW_UP => up]").contains(is_held));
+        // Separate key handlers are released independently
+        assert!(
+            !startup_code("kh: Kh, document, [KEY_W => up, ARROW_UP => up]").contains("any (|")
+        );
+    }
+
+    #[test]
+    fn consumption_attributes_are_parsed() {
+        let key_handler = syn::parse_str::<KeyHandler>(
+            "#[prevent_default] #[stop_propagation = false] KEY_A => a",
+        )
+        .unwrap();
+        let Consumption {
+            prevent_default,
+            stop_propagation,
+        } = key_handler.consumption;
+        assert_eq!(
+            (prevent_default, stop_propagation),
+            (Some(true), Some(false))
+        );
+        assert_eq!(
+            parse_error("#[prevent] KEY_A => a"),
+            "unknown attribute, expected `prevent_default` or `stop_propagation`"
+        );
+        assert_eq!(
+            parse_error("#[prevent_default = 1] KEY_A => a"),
+            "expected either no value or a `bool` literal, e.g. `#[prevent_default = false]`"
+        );
+        assert_eq!(
+            parse_error("#[stop_propagation] #[stop_propagation] KEY_A => a"),
+            "attribute is specified more than once"
+        );
+        assert_eq!(
+            parse_error("#[stop_propagation] .. => observer"),
+            "only key handlers and prefixes can have attributes"
+        );
+        assert_eq!(
+            options_error("prevent_default = true, prevent_default = false"),
+            "option `prevent_default` is specified more than once"
+        );
+        assert_eq!(
+            options_error("stop_propagation = yes"),
+            "expected boolean literal"
+        );
+    }
+
+    #[test]
+    fn consumption_attributes_override_the_options() {
+        let args = syn::parse_str::<Args>(
+            "kh: Kh, document, [\
+                KEY_A => a, \
+                #[prevent_default = false] KEY_B => b, \
+                #[stop_propagation] Ctrl+KEY_X => [KEY_C => c]\
+            ], prevent_default = true",
+        )
+        .unwrap();
+        let consumption = |candidate: Candidate| {
+            let mut ts = proc_macro2::TokenStream::new();
+            args.extend_with_consumption(&mut ts, candidate);
+            ts.to_string()
+        };
+        assert_eq!(
+            consumption(Candidate::KeyHandler(0, 0)),
+            "event . prevent_default () ;"
+        );
+        assert_eq!(consumption(Candidate::KeyHandler(1, 0)), "");
+        // The key handlers that follow the prefix don't inherit its attributes
+        assert_eq!(
+            consumption(Candidate::KeyHandler(2, 0)),
+            "event . prevent_default () ;"
+        );
+        assert_eq!(
+            consumption(Candidate::Prefix(0)),
+            "event . prevent_default () ; event . stop_propagation () ;"
+        );
+    }
+
+    #[test]
+    fn listener_options_are_parsed() {
+        let args = syn::parse_str::<Args>(
+            "kh: Kh, document, [KEY_A => a], once = false, capture = is_capturing(),",
+        )
+        .unwrap();
+        let listener_options = args
+            .options
+            .listener_options()
+            .into_iter()
+            .map(|(name, value)| format!("{} = {}", name, quote!(#value)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            listener_options,
+            ["capture = is_capturing ()", "once = false"]
+        );
+        assert_eq!(
+            options_error("passive = true, passive = true"),
+            "option `passive` is specified more than once"
+        );
+        assert!(options_error("capturing = true").starts_with("unknown option `capturing`"));
+    }
+
+    #[test]
+    fn listeners_are_removed_in_the_phase_they_were_added_in() {
+        let with_options =
+            startup_code("kh: Kh, document, [KEY_A => a], capture = true, passive = x");
+        assert!(with_options.contains("let __capture : bool = true ;"));
+        assert!(with_options.contains(
+            "__listener_options . capture (__capture) ; __listener_options . passive (x) ;"
+        ));
+        assert!(with_options
+            .contains("add_event_listener_with_callback_and_add_event_listener_options"));
+        assert!(with_options.contains("remove_event_listener_with_callback_and_bool"));
+        // `capture` defaults to `false` when only other options are given
+        assert!(startup_code("kh: Kh, document, [KEY_A => a], once = true")
+            .contains("let __capture : bool = false ;"));
+        let without_options = startup_code("kh: Kh, document, [KEY_A => a]");
+        assert!(!without_options.contains("AddEventListenerOptions"));
+    }
+
+    #[test]
+    fn any_expression_is_a_target() {
+        for target in [
+            "document",
+            "web_sys::window().unwrap()",
+            "&canvas",
+            "if is_global { &window } else { &canvas }",
+        ] {
+            let input = format!("kh: Kh, {}, [KEY_A => a]", target);
+            let args = syn::parse_str::<Args>(&input).unwrap();
+            let parsed = &args.target;
+            let expected = syn::parse_str::<Expr>(target).unwrap();
+            assert_eq!(quote!(#parsed).to_string(), quote!(#expected).to_string());
+        }
+        assert!(syn::parse_str::<Args>("kh: Kh, [KEY_A => a]").is_err());
+    }
+
+    #[test]
+    fn target_is_evaluated_as_a_whole() {
+        let startup_code = startup_code("kh: Kh, canvas as HtmlElement, [KEY_A => a]");
+        // Without the parentheses, the reference would be taken to `canvas` only
+        assert!(startup_code
+            .contains("let __target : & :: web_sys :: EventTarget = & (canvas as HtmlElement) ;"));
+        assert!(startup_code.contains("target : __target . clone () ,"));
     }
 }